@@ -11,12 +11,24 @@ let
     else
       toStr port;
 
+  # Auto picks nftables since the iptables package uses nftables itself
+  useNftables = cfg.settings.killswitch.backend != "Iptables";
+
   generateRule = proto: port:
-    map
-    (chain: "-A ${chain} -p ${proto} --dport ${formatPort port} -j ACCEPT") [
-      "INPUT"
-      "OUTPUT"
-    ];
+    if useNftables then
+      map (chain:
+        "add rule inet protonvpn_rs ${chain} ${proto} dport ${
+          lib.replaceStrings [ ":" ] [ "-" ] (formatPort port)
+        } accept") [
+          "input"
+          "output"
+        ]
+    else
+      map
+      (chain: "-A ${chain} -p ${proto} --dport ${formatPort port} -j ACCEPT") [
+        "INPUT"
+        "OUTPUT"
+      ];
 
   generateRules = proto: ports:
     lib.flatten (map (port: generateRule proto port) ports);
@@ -59,8 +71,12 @@ let
     inherit (ron.types) str option array;
   in ron.format {
     inherit (settings)
-      max_cache_age autostart_default default_select default_protocol;
+      max_cache_age server_refresh_interval autostart_default default_select default_protocol
+      connect_timeout resume disable_ipv6;
+    startup = option settings.startup;
     credentials_path = option (str settings.credentials_path);
+    socket_group = option
+      (if settings.socket_group == null then null else str settings.socket_group);
     update_resolv_conf_path = option (str settings.update_resolv_conf_path);
 
     default_criteria = with settings.default_criteria; {
@@ -69,8 +85,25 @@ let
       features = array features;
     };
 
+    reconnect = { inherit (settings.reconnect) enable max_retries backoff; };
+    failover = { inherit (settings.failover) max_servers try_other_protocol; };
+    hooks = with settings.hooks; {
+      inherit timeout;
+      pre_connect = array (map str pre_connect);
+      post_connect = array (map str post_connect);
+      pre_disconnect = array (map str pre_disconnect);
+      post_disconnect = array (map str post_disconnect);
+      on_failure = array (map str on_failure);
+      on_killswitch_change = array (map str on_killswitch_change);
+    };
+    notifications = {
+      inherit (settings.notifications)
+        connected disconnected dropped killswitch auth_failed;
+    };
+
     killswitch = with settings.killswitch; {
-      inherit enable;
+      inherit enable backend permanent;
+      dns = array (map str dns);
       custom_rules = option (array (map str
         (custom_rules ++ (lib.optionals applyFirewallRules allRules))));
     };
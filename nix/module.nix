@@ -24,6 +24,13 @@ in {
           Maximum cache age in days
         '';
       };
+      server_refresh_interval = mkOption {
+        type = types.number;
+        default = 60;
+        description = mkDoc ''
+          Minutes between server list refreshes in the daemon, `0` disables them
+        '';
+      };
       autostart_default = mkOption {
         type = types.bool;
         default = false;
@@ -31,6 +38,14 @@ in {
           Automatically connect to a server matching the default criteria on startup.
         '';
       };
+      startup = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        description = mkDoc ''
+          Which server to connect to on startup, overrides `autostart_default` when set.
+          Choice of: [None, DefaultCriteria, LastUsed, Profile("name")]
+        '';
+      };
       credentials_path = mkOption {
         type = types.nullOr types.str;
         default = null;
@@ -38,6 +53,21 @@ in {
           Path to the openvpn authentication credentials
         '';
       };
+      socket_group = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        description = mkDoc ''
+          Group that owns the daemon's socket, its members can use the cli without root.
+          The group is created if it doesn't exist.
+        '';
+      };
+      connect_timeout = mkOption {
+        type = types.number;
+        default = 30;
+        description = mkDoc ''
+          Seconds to wait for the tunnel to come up before a connection attempt is aborted
+        '';
+      };
       update_resolv_conf_path = mkOption {
         type = types.path;
         default =
@@ -54,6 +84,14 @@ in {
           Choice of: [Fastest, Random]
         '';
       };
+      resume = mkOption {
+        type = types.enum [ "Never" "Adopt" "Reconnect" ];
+        default = "Reconnect";
+        description = mkDoc ''
+          What to do with the previous connection when the daemon restarts.
+          `Adopt` takes over the openvpn process if it's still running and leaves it running when the daemon stops.
+        '';
+      };
       default_protocol = mkOption {
         type = types.str;
         default = "Udp";
@@ -71,7 +109,120 @@ in {
         custom_rules = mkOption {
           type = types.nullOr (types.listOf types.str);
           default = null;
+          description = mkDoc ''
+            Extra rules in the syntax of the firewall backend that's used
+          '';
+        };
+        backend = mkOption {
+          type = types.enum [ "Auto" "Iptables" "Nftables" ];
+          default = "Auto";
+          description = mkDoc ''
+            Firewall used for the killswitch, `Auto` prefers nftables when `iptables` uses nftables itself
+          '';
+        };
+        permanent = mkOption {
+          type = types.bool;
+          default = false;
+          description = mkDoc ''
+            Keep the killswitch active while disconnected, after the daemon stopped and from boot on
+          '';
         };
+        dns = mkOption {
+          type = types.listOf types.str;
+          default = [ ];
+          description = mkDoc ''
+            Resolvers that stay reachable while the permanent killswitch blocks everything else
+          '';
+        };
+      };
+
+      disable_ipv6 = mkOption {
+        type = types.bool;
+        default = false;
+        description = mkDoc ''
+          Disable ipv6 on every interface except the tunnel while connected
+        '';
+      };
+
+      reconnect = {
+        enable = mkEnableOption (mdDoc ''
+          Reconnect to the same server when the openvpn process exits unexpectedly
+        '');
+        max_retries = mkOption {
+          type = types.number;
+          default = 3;
+        };
+        backoff = mkOption {
+          type = types.number;
+          default = 5;
+          description = mkDoc ''
+            Seconds to wait before the first retry, doubled after every failed attempt
+          '';
+        };
+      };
+
+      notifications = {
+        connected = mkOption {
+          type = types.bool;
+          default = true;
+        };
+        disconnected = mkOption {
+          type = types.bool;
+          default = true;
+        };
+        dropped = mkOption {
+          type = types.bool;
+          default = true;
+          description = mkDoc ''
+            Notify when the openvpn process exits without being asked to
+          '';
+        };
+        killswitch = mkOption {
+          type = types.bool;
+          default = true;
+        };
+        auth_failed = mkOption {
+          type = types.bool;
+          default = true;
+        };
+      };
+
+      hooks = let
+        hookOption = description:
+          mkOption {
+            type = types.listOf types.str;
+            default = [ ];
+            description = mkDoc description;
+          };
+      in {
+        pre_connect = hookOption "Commands to run before openvpn is started";
+        post_connect = hookOption "Commands to run once the tunnel is up";
+        pre_disconnect = hookOption "Commands to run before disconnecting";
+        post_disconnect = hookOption "Commands to run after disconnecting";
+        on_failure = hookOption
+          "Commands to run when connecting fails or openvpn exits unexpectedly";
+        on_killswitch_change =
+          hookOption "Commands to run when the killswitch is enabled or disabled";
+        timeout = mkOption {
+          type = types.number;
+          default = 10;
+          description = mkDoc ''
+            Seconds a hook command may run before it's killed
+          '';
+        };
+      };
+
+      failover = {
+        max_servers = mkOption {
+          type = types.number;
+          default = 3;
+          description = mkDoc ''
+            Maximum amount of servers to try when connecting fails, ranked by `default_select`
+          '';
+        };
+        try_other_protocol = mkEnableOption (mdDoc ''
+          Retry with the other protocol before moving on to the next server
+        '');
       };
 
       default_criteria = {
@@ -106,23 +257,47 @@ in {
   config = {
     environment.systemPackages = [ protonvpn-rs ];
 
+    users.groups = lib.optionalAttrs (cfg.enable && cfg.settings.socket_group != null) {
+      ${cfg.settings.socket_group} = { };
+    };
+
     systemd.services.protonvpn-rs = lib.mkIf cfg.enable {
       description = "${serviceName} service";
       after = [ ] ++ lib.optionals cfg.requireSops [ "decrypt-sops.service" ];
 
-      path = with pkgs; [ openvpn iptables ];
+      path = with pkgs; [ openvpn iptables nftables ];
       serviceConfig = {
         User = "root";
         Group = "root";
 
         ExecStart =
           "${protonvpn-rs}/bin/protonvpn-rs service start --daemon --verbose";
+        ExecReload = "${pkgs.coreutils}/bin/kill -HUP $MAINPID";
         Type = "simple";
         RemainAfterExit = true;
+        # openvpn is kept running to be adopted after a restart
+        KillMode = lib.mkIf (cfg.settings.resume == "Adopt") "process";
       };
       wantedBy = [ "multi-user.target" ];
     };
 
+    systemd.services.protonvpn-rs-lockdown =
+      lib.mkIf (cfg.enable && cfg.settings.killswitch.permanent) {
+        description = "${serviceName} permanent killswitch";
+        unitConfig.DefaultDependencies = false;
+        before = [ "network-pre.target" ];
+        wants = [ "network-pre.target" ];
+        after = [ "local-fs.target" ];
+
+        path = with pkgs; [ iptables nftables ];
+        serviceConfig = {
+          ExecStart = "${protonvpn-rs}/bin/protonvpn-rs killswitch lockdown";
+          Type = "oneshot";
+          RemainAfterExit = true;
+        };
+        wantedBy = [ "sysinit.target" ];
+      };
+
     systemd.services.networking = lib.mkIf cfg.enable {
       after = [ "${serviceName}.service" ];
       requires = [ "${serviceName}.service" ];
@@ -1,4 +1,5 @@
-{ lib, makeRustPlatform, rust-bin, buildInputs, nativeBuildInputs, ... }:
+{ lib, makeRustPlatform, makeWrapper, glib, rust-bin, buildInputs
+, nativeBuildInputs, ... }:
 let
   manifest = (lib.importTOML ../Cargo.toml).package;
   rustPlatform = makeRustPlatform {
@@ -6,13 +7,19 @@ let
     rustc = rust-bin.stable.latest.minimal;
   };
 in rustPlatform.buildRustPackage rec {
-  inherit buildInputs nativeBuildInputs;
+  inherit buildInputs;
   inherit (manifest) name version;
+  nativeBuildInputs = nativeBuildInputs ++ [ makeWrapper ];
 
   src = ../.;
 
+  # `gdbus` sends the desktop notifications of `pvpn notify`
   postInstall = # sh
-    "ln -sf $out/bin/${manifest.name} $out/bin/pvpn";
+    ''
+      wrapProgram $out/bin/${manifest.name} \
+        --prefix PATH : ${lib.makeBinPath [ glib.bin ]}
+      ln -sf $out/bin/${manifest.name} $out/bin/pvpn
+    '';
 
   cargoLock = {
     lockFile = ../Cargo.lock;
@@ -3,7 +3,7 @@ pub mod types;
 use crate::config::FlattenBitflagEnum;
 use anyhow::Result;
 use clap::ValueEnum;
-use rand::seq::IteratorRandom;
+use rand::seq::SliceRandom;
 use serde::{self, Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -18,7 +18,7 @@ use crate::{
     config::{Filters, Select},
 };
 
-const HOST: &str = "https://api.protonmail.ch";
+pub const DOMAIN: &str = "api.protonmail.ch";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LogicalServers(pub Arc<[LogicalServer]>);
@@ -45,7 +45,7 @@ impl LogicalServer {
     }
 }
 
-#[derive(ValueEnum, Clone, Debug, Deserialize, Serialize)]
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Ordering {
     Speed,
     Load,
@@ -60,7 +60,7 @@ impl LogicalServers {
         self.0.iter().map(|x| (x.id.as_str(), x)).collect()
     }
 
-    pub fn to_filtered(&self, filter: &Filters) -> FilteredLogicalServers {
+    pub fn to_filtered(&self, filter: &Filters) -> FilteredLogicalServers<'_> {
         FilteredLogicalServers(
             self.iter()
                 .filter(|s| s.matches_filters(filter))
@@ -84,19 +84,22 @@ impl<'a> FilteredLogicalServers<'a> {
         self
     }
 
-    pub fn select(self, value: &Select) -> Option<&'a LogicalServer> {
+    /// Orders the servers by preference, the first server is the one `select` would return.
+    pub fn rank(self, value: &Select) -> Vec<&'a LogicalServer> {
         match value {
-            Select::Fastest => {
-                let servers = self.sort_by(&Ordering::Speed);
-                servers.0.into_iter().nth(0)
-            }
-            Select::LeastLoad => {
-                let servers = self.sort_by(&Ordering::Load);
-                servers.0.into_iter().nth(0)
+            Select::Fastest => self.sort_by(&Ordering::Speed).0,
+            Select::LeastLoad => self.sort_by(&Ordering::Load).0,
+            Select::Random => {
+                let mut servers = self.0;
+                servers.shuffle(&mut rand::thread_rng());
+                servers
             }
-            Select::Random => self.0.into_iter().choose(&mut rand::thread_rng()),
         }
     }
+
+    pub fn select(self, value: &Select) -> Option<&'a LogicalServer> {
+        self.rank(value).into_iter().nth(0)
+    }
 }
 
 impl LogicalServer {
@@ -114,7 +117,7 @@ struct ServerResponse {
 }
 
 fn call_api(endpoint: &str) -> Result<String> {
-    let url = format!("{HOST}/api/{endpoint}");
+    let url = format!("https://{DOMAIN}/api/{endpoint}");
     Ok(reqwest::blocking::get(url)?.text()?)
 }
 
@@ -125,8 +128,13 @@ pub fn logicals() -> Result<LogicalServers> {
         return Ok(content);
     }
 
+    fetch_logicals()
+}
+
+/// Always asks the api for the current server list, ignoring the cache, and updates the cache
+pub fn fetch_logicals() -> Result<LogicalServers> {
     let response = call_api("vpn/logicals")?;
-    let data = serde_json::from_str::<ServerResponse>(response.as_str()).unwrap();
+    let data = serde_json::from_str::<ServerResponse>(response.as_str())?;
     let logical_servers = LogicalServers::new(
         data.logical_servers
             .into_iter()
@@ -2,7 +2,7 @@ use clap::ValueEnum;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::net::Ipv4Addr;
 
-#[derive(Debug, Default, Deserialize, Serialize, Clone, ValueEnum)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq, ValueEnum)]
 pub enum Tier {
     Free,
     #[default]
@@ -51,7 +51,7 @@ impl<'de> Deserialize<'de> for Features {
 //     long: f64,
 // }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Server {
     #[serde(rename = "EntryIP")]
     pub entry_ip: Ipv4Addr,
@@ -83,7 +83,7 @@ pub struct Server {
     // pub services_down_reason: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LogicalServer {
     #[serde(rename = "Name")]
     pub name: String,
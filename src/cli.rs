@@ -1,16 +1,22 @@
 use colored::Colorize;
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{path::PathBuf, time::Duration};
 
 use anyhow::Result;
-use clap::{builder::EnumValueParser, command, value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap::{
+    builder::EnumValueParser, command, value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum,
+};
+use serde::Serialize;
 
 use crate::{
-    api::{self, Country, FilteredLogicalServers, LogicalServers, Ordering, Tier},
+    api::{Country, LogicalServer, Ordering, Tier},
     cache,
-    client::{self, openvpn::Protocol},
+    client::openvpn::Protocol,
     config::{self, Configuration, FeatureEnum, Filters, Select},
     daemon,
-    protocol::{Request, Response, ServerStatus, SocketProtocol},
+    history::Filter,
+    killswitch,
+    notify::Notifier,
+    protocol::{ConnectionInfo, Request, Response, ServerStatus},
     service, utils,
 };
 
@@ -30,9 +36,33 @@ pub fn init() -> Command {
         .subcommand(init_disconnect_subcommand())
         .subcommand(init_status_subcommand())
         .subcommand(init_query_subcommand())
+        .subcommand(init_refresh_subcommand())
         .subcommand(init_service_subcommand())
         .subcommand(init_config_subcommand())
         .subcommand(init_killswitch_subcommand())
+        .subcommand(init_watch_subcommand())
+        .subcommand(init_history_subcommand())
+        .subcommand(init_notify_subcommand())
+}
+
+/// Subcommands that run the daemon or write system files, escalated before running them
+pub fn requires_root(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("service", args)) => match args.subcommand() {
+            Some(("install", args)) => {
+                matches!(args.get_one::<bool>("write"), Some(true))
+                    && args.get_one::<PathBuf>("path").is_none()
+            }
+            Some(("start" | "stop" | "uninstall", _)) => true,
+            _ => false,
+        },
+        Some(("config", args)) => match args.subcommand() {
+            Some(("writedefault", args)) => args.get_one::<PathBuf>("path").is_none(),
+            _ => false,
+        },
+        Some(("killswitch", args)) => matches!(args.subcommand(), Some(("lockdown", _))),
+        _ => false,
+    }
 }
 
 fn init_filter_args() -> [Arg; 8] {
@@ -79,10 +109,8 @@ fn init_filter_args() -> [Arg; 8] {
     ]
 }
 
-fn filter_servers<'a>(
-    servers: &'a LogicalServers,
-    args: &ArgMatches,
-) -> FilteredLogicalServers<'a> {
+/// Builds the search criteria from the filter flags, falling back to `default_criteria`
+fn filter_criteria(args: &ArgMatches) -> Filters {
     let config = config::read().expect("config to be written");
     let mut features: Vec<FeatureEnum> = Vec::new();
 
@@ -121,12 +149,12 @@ fn filter_servers<'a>(
         .unwrap_or(&config.default_criteria.max_load)
         .to_owned();
 
-    servers.to_filtered(&Filters {
+    Filters {
         country,
         tier,
         max_load,
         features,
-    })
+    }
 }
 
 pub fn init_connect_subcommand() -> Command {
@@ -168,8 +196,7 @@ pub fn init_connect_subcommand() -> Command {
 
 pub fn handle_connect_subcommand(args: &ArgMatches) -> Result<()> {
     let config = config::read()?;
-    let servers = api::logicals()?;
-    let servers = filter_servers(&servers, args);
+    let criteria = filter_criteria(args);
 
     let select = if let Some(true) = args.get_one::<bool>("fastest") {
         Select::Fastest
@@ -181,18 +208,30 @@ pub fn handle_connect_subcommand(args: &ArgMatches) -> Result<()> {
         config.default_select.to_owned()
     };
 
-    let server = servers
-        .select(&select)
-        .expect("No servers matching search criteria");
-
-    println!("Connecting to {}!", &server.name);
-
     let protocol = match args.get_one::<Protocol>("protocol") {
         Some(protocol) => protocol.to_owned(),
         None => config.default_protocol,
     };
-    let req = Request::Connect(server.id.clone(), protocol);
-    daemon::send_request(req)?;
+
+    match daemon::send_request(Request::ConnectMatching(criteria, select, protocol))? {
+        Response::Connected {
+            server,
+            protocol,
+            failed,
+        } => {
+            for attempt in failed {
+                println!(
+                    "{} Failed to connect to {} ({}): {}",
+                    "●".yellow(),
+                    attempt.server,
+                    attempt.protocol,
+                    attempt.error
+                );
+            }
+            println!("Connected to {} ({})", server, protocol);
+        }
+        res => anyhow::bail!("unexpected response from daemon: {res:?}"),
+    }
 
     Ok(())
 }
@@ -202,23 +241,55 @@ pub fn init_query_subcommand() -> Command {
         .about("Query servers")
         .visible_alias("q")
         .args(init_filter_args())
+        .arg(
+            Arg::new("limit")
+                .short('l')
+                .long("limit")
+                .help("Only show the first servers")
+                .value_parser(value_parser!(usize)),
+        )
 }
 
 pub fn handle_query_subcommand(args: &ArgMatches) -> Result<()> {
-    let servers = api::logicals()?;
-    let servers = filter_servers(&servers, args);
+    let criteria = filter_criteria(args);
+    let ordering = args.get_one::<Ordering>("sort").cloned();
+    let limit = args.get_one::<usize>("limit").copied();
+
+    let servers = match daemon::send_request(Request::Query(criteria, ordering, limit))? {
+        Response::Servers(servers) => servers,
+        res => anyhow::bail!("unexpected response from daemon: {res:?}"),
+    };
 
     let pretty_config = ron::ser::PrettyConfig::default();
-    let formatted = ron::ser::to_string_pretty::<FilteredLogicalServers>(&servers, pretty_config)?;
+    let formatted = ron::ser::to_string_pretty::<Vec<LogicalServer>>(&servers, pretty_config)?;
     println!("{}", formatted);
 
     Ok(())
 }
 
+pub fn init_refresh_subcommand() -> Command {
+    Command::new("refresh").about("Fetch the latest server list and server loads")
+}
+
+pub fn handle_refresh_subcommand(_args: &ArgMatches) -> Result<()> {
+    match daemon::send_request(Request::RefreshServers)? {
+        Response::Refreshed { servers } => {
+            println!("Refreshed server list, {servers} servers available")
+        }
+        res => anyhow::bail!("unexpected response from daemon: {res:?}"),
+    }
+
+    Ok(())
+}
+
 pub fn init_status_subcommand() -> Command {
     Command::new("status")
         .visible_alias("s")
         .about("Get info about current connection status")
+        .long_about(
+            "Get info about current connection status\n\
+            Exits with 0 when connected, 3 when disconnected or connecting and 4 when the daemon can't be reached",
+        )
         .arg(
             Arg::new("ip")
                 .long("ip")
@@ -226,55 +297,390 @@ pub fn init_status_subcommand() -> Command {
                 .action(ArgAction::SetTrue)
                 .value_parser(value_parser!(bool)),
         )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Output format")
+                .default_value("table")
+                .value_parser(EnumValueParser::<StatusFormat>::new()),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .help("Keep running and print the status every time it changes")
+                .action(ArgAction::SetTrue)
+                .value_parser(value_parser!(bool)),
+        )
+        .arg(
+            Arg::new("bar")
+                .long("bar")
+                .help("Keep running and print a line in the status bar's format every time the status changes")
+                .conflicts_with_all(["format", "watch"])
+                .value_parser(EnumValueParser::<Bar>::new()),
+        )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Bar {
+    Waybar,
+    Polybar,
+    I3blocks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFormat {
+    Table,
+    /// Uncolored `key: value` lines with the same keys as the json output
+    Plain,
+    Json,
+    Ron,
+}
+
+/// Status as printed by `status --format json|ron|plain`, scripts rely on these field names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum StatusReport {
+    Connected(ConnectionInfo),
+    Connecting { name: String, protocol: Protocol },
+    Disconnected { last_exit: Option<String> },
+    Unreachable { error: String },
+}
+
+impl StatusReport {
+    /// The fields of the json output as `(key, value)` pairs, unset fields are left out
+    pub fn plain_lines(&self) -> Result<Vec<(String, String)>> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self)? else {
+            anyhow::bail!("status isn't an object");
+        };
+
+        let mut lines = fields
+            .into_iter()
+            .filter_map(|(key, value)| match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(value) => Some((key, value)),
+                value => Some((key, value.to_string())),
+            })
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|(key, _)| key != "status");
+
+        Ok(lines)
+    }
+
+    pub fn fetch() -> Self {
+        let res = match daemon::send_request(Request::Status) {
+            Ok(res) => res,
+            Err(err) => {
+                return Self::Unreachable {
+                    error: err.to_string(),
+                }
+            }
+        };
+
+        match res {
+            Response::Status(ServerStatus::Connected(info)) => Self::Connected(info),
+            Response::Status(ServerStatus::Connecting { name, protocol }) => {
+                Self::Connecting { name, protocol }
+            }
+            Response::Status(ServerStatus::Disconnected { last_exit }) => {
+                Self::Disconnected { last_exit }
+            }
+            res => Self::Unreachable {
+                error: format!("unexpected response from daemon: {res:?}"),
+            },
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Connected(_) => 0,
+            Self::Connecting { .. } | Self::Disconnected { .. } => 3,
+            Self::Unreachable { .. } => 4,
+        }
+    }
 }
 
 pub fn handle_status_subcommand(args: &ArgMatches) -> Result<()> {
-    let mut res = match daemon::send_request(Request::Status) {
-        Ok(res) => res,
-        Err(_) => {
-            println!("{} Status dead", "●".red());
-            return Ok(());
+    let format = args
+        .get_one::<StatusFormat>("format")
+        .copied()
+        .unwrap_or(StatusFormat::Table);
+    let show_ip = matches!(args.get_one::<bool>("ip"), Some(true));
+
+    if let Some(bar) = args.get_one::<Bar>("bar") {
+        return handle_bar_output(*bar);
+    }
+
+    let mut report = StatusReport::fetch();
+
+    if let Some(true) = args.get_one::<bool>("watch") {
+        print_status(&report, format, show_ip, true)?;
+
+        // The iterator ends when the daemon goes away, the last report will be `Unreachable`
+        let events = match daemon::subscribe() {
+            Ok(events) => events,
+            Err(_) => std::process::exit(report.exit_code()),
+        };
+        for _event in events.map_while(Result::ok) {
+            let next = StatusReport::fetch();
+            if next != report {
+                print_status(&next, format, show_ip, true)?;
+                report = next;
+            }
+        }
+
+        report = StatusReport::fetch();
+        print_status(&report, format, show_ip, true)?;
+    } else {
+        print_status(&report, format, show_ip, false)?;
+    }
+
+    std::process::exit(report.exit_code());
+}
+
+/// Runs until killed by the bar, waits for the daemon to come back when it goes away.
+fn handle_bar_output(bar: Bar) -> Result<()> {
+    let mut last = None;
+    let mut print = |report: &StatusReport| {
+        let line = bar_output(report, bar);
+        if last.as_ref() != Some(&line) {
+            println!("{line}");
+            last = Some(line);
         }
     };
-    let buf = &mut String::new();
-    res.read_to_string(buf)?;
-
-    match Response::deserialize(buf)? {
-        Response::Status(status) => match status {
-            ServerStatus::Connected {
-                name,
-                pid,
-                protocol,
-            } => {
-                let logfile = File::open(cache::get_path().join("ovpn.log"))?;
-                let nic = client::openvpn::parse_nic(logfile);
-
-                let interface = match utils::find_nic(&nic.expect("to find nic")) {
-                    Some(interface) => {
-                        let entry_ips = *interface.ips.first().unwrap();
-                        format!("{} {}", interface.name, entry_ips)
-                    }
-                    None => "Network interface not found! your ip is exposed".to_string(),
-                };
-                println!("{} Status connected", "●".green());
-                let mut status = StatusTable::new(vec![
-                    ("Server", &name),
-                    ("Protocol", &protocol.to_string()),
-                    ("OpenVPN PID", &pid.to_string()),
-                    ("Interface", interface.as_str()),
-                ]);
-
-                if let Some(true) = args.get_one::<bool>("ip") {
-                    let info = utils::lookup_ip()?;
-                    status.push(("Public IP", &info.ip.to_string()))
+
+    loop {
+        print(&StatusReport::fetch());
+
+        if let Ok(events) = daemon::subscribe() {
+            for _event in events.map_while(Result::ok) {
+                print(&StatusReport::fetch());
+            }
+            print(&StatusReport::fetch());
+        }
+
+        std::thread::sleep(Duration::from_secs(5));
+    }
+}
+
+const COLOR_CONNECTED: &str = "#a3be8c";
+const COLOR_CONNECTING: &str = "#ebcb8b";
+const COLOR_DISCONNECTED: &str = "#bf616a";
+
+/// Formats the status as a single line in the bar's native format
+pub fn bar_output(report: &StatusReport, bar: Bar) -> String {
+    let (text, short, class, color) = match report {
+        StatusReport::Connected(info) => {
+            let country = format!("{:?}", info.country);
+            let mut text = format!("{} {}", country_flag(&country), info.name);
+            if info.killswitch {
+                text.push_str(" 🔒");
+            }
+            (text, country, "connected", COLOR_CONNECTED)
+        }
+        StatusReport::Connecting { name, .. } => (
+            format!("… {name}"),
+            "…".to_string(),
+            "connecting",
+            COLOR_CONNECTING,
+        ),
+        StatusReport::Disconnected { .. } => (
+            "disconnected".to_string(),
+            "off".to_string(),
+            "disconnected",
+            COLOR_DISCONNECTED,
+        ),
+        StatusReport::Unreachable { .. } => (
+            "daemon down".to_string(),
+            "down".to_string(),
+            "unreachable",
+            COLOR_DISCONNECTED,
+        ),
+    };
+
+    match bar {
+        Bar::Waybar => serde_json::json!({
+            "text": text,
+            "alt": class,
+            "tooltip": bar_tooltip(report),
+            "class": class,
+        })
+        .to_string(),
+        Bar::Polybar => format!("%{{F{color}}}{text}%{{F-}}"),
+        // Expects `format=json` and `interval=persist` in the block's config
+        Bar::I3blocks => serde_json::json!({
+            "full_text": text,
+            "short_text": short,
+            "color": color,
+        })
+        .to_string(),
+    }
+}
+
+fn bar_tooltip(report: &StatusReport) -> String {
+    match report {
+        StatusReport::Connected(info) => {
+            let mut lines = vec![
+                format!("Server: {} ({:?})", info.name, info.country),
+                format!("Protocol: {}", info.protocol),
+                format!("Load: {}%", info.load),
+            ];
+            if let Some(ip) = info.tunnel_ip {
+                lines.push(format!("Tunnel IP: {ip}"));
+            }
+            lines.push(format!(
+                "Killswitch: {}",
+                match info.killswitch {
+                    true => "enabled",
+                    false => "disabled",
                 }
+            ));
+            lines.join("\n")
+        }
+        StatusReport::Connecting { name, protocol } => format!("Connecting to {name} ({protocol})"),
+        StatusReport::Disconnected { last_exit: None } => "Disconnected".to_string(),
+        StatusReport::Disconnected {
+            last_exit: Some(reason),
+        } => format!("Disconnected, openvpn exited: {reason}"),
+        StatusReport::Unreachable { error } => format!("Daemon unreachable: {error}"),
+    }
+}
+
+/// Turns a country code into its flag emoji, proton uses `UK` instead of `GB`
+pub fn country_flag(code: &str) -> String {
+    let code = match code {
+        "UK" => "GB",
+        code => code,
+    };
 
-                status.print_lines()
+    code.chars()
+        .filter(|char| char.is_ascii_alphabetic())
+        .filter_map(|char| {
+            char::from_u32(0x1F1E6 + (char.to_ascii_uppercase() as u32 - 'A' as u32))
+        })
+        .collect()
+}
+
+fn print_status(
+    report: &StatusReport,
+    format: StatusFormat,
+    show_ip: bool,
+    redraw: bool,
+) -> Result<()> {
+    match format {
+        StatusFormat::Json => println!("{}", serde_json::to_string(report)?),
+        StatusFormat::Ron => println!("{}", ron::to_string(report)?),
+        StatusFormat::Plain => {
+            for (key, value) in report.plain_lines()? {
+                println!("{key}: {value}");
             }
-            ServerStatus::Disconnected => {
-                println!("{} Status disconnected", "●".red());
+            if show_ip {
+                println!("public_ip: {}", utils::lookup_ip()?.ip);
             }
-        },
+            // Separates the reports while watching
+            if redraw {
+                println!();
+            }
+        }
+        StatusFormat::Table => {
+            if redraw {
+                // Clear the screen and move the cursor to the top left
+                print!("\x1b[2J\x1b[H");
+            }
+            print_status_table(report, show_ip)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_status_table(report: &StatusReport, show_ip: bool) -> Result<()> {
+    match report {
+        StatusReport::Connected(info) => {
+            println!("{} Status connected", "●".green());
+
+            let now = utils::unix_timestamp(std::time::SystemTime::now());
+            let uptime = Duration::from_secs(now.saturating_sub(info.connected_since));
+            let features = info
+                .features
+                .iter_names()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            let interface = match info.interface {
+                Some(ref interface) => match info.tunnel_ip {
+                    Some(ip) => format!("{interface} {ip}"),
+                    None => interface.to_owned(),
+                },
+                None => "Network interface not found! your ip is exposed".to_string(),
+            };
+
+            let mut status = StatusTable::new(vec![
+                ("Server", &info.name),
+                ("Country", &format!("{:?}", info.country)),
+                ("Load", &format!("{}%", info.load)),
+                ("Protocol", &info.protocol.to_string()),
+                ("OpenVPN PID", &info.pid.to_string()),
+                ("Interface", interface.as_str()),
+            ]);
+
+            if !features.is_empty() {
+                status.push(("Features", &features.join(", ")));
+            }
+
+            if let Some(ref state) = info.state {
+                status.push(("State", &state.to_string()));
+            }
+
+            if let Some(remote) = info.remote {
+                status.push(("Remote", &remote.to_string()));
+            }
+
+            status.push((
+                "Connected",
+                &format!(
+                    "{} UTC ({})",
+                    utils::format_timestamp(info.connected_since),
+                    utils::format_duration(uptime)
+                ),
+            ));
+            status.push((
+                "Traffic",
+                &format!(
+                    "{} in / {} out",
+                    utils::format_bytes(info.bytes_in),
+                    utils::format_bytes(info.bytes_out)
+                ),
+            ));
+            status.push((
+                "Killswitch",
+                match info.killswitch {
+                    true => "enabled",
+                    false => "disabled",
+                },
+            ));
+
+            if show_ip {
+                let info = utils::lookup_ip()?;
+                status.push(("Public IP", &info.ip.to_string()))
+            }
+
+            status.print_lines()
+        }
+        StatusReport::Connecting { name, protocol } => {
+            println!("{} Status connecting", "●".yellow());
+            StatusTable::new(vec![("Server", name), ("Protocol", &protocol.to_string())])
+                .print_lines()
+        }
+        StatusReport::Disconnected { last_exit } => {
+            println!("{} Status disconnected", "●".red());
+            if let Some(reason) = last_exit {
+                StatusTable::new(vec![("Last exit", reason)]).print_lines()
+            }
+        }
+        StatusReport::Unreachable { .. } => {
+            println!("{} Status dead", "●".red());
+        }
     };
 
     Ok(())
@@ -331,6 +737,14 @@ pub fn init_service_subcommand() -> Command {
                         .help("Write the contents instead of printing to stdout")
                         .action(ArgAction::SetTrue)
                         .value_parser(value_parser!(bool)),
+                )
+                .arg(
+                    Arg::new("lockdown")
+                        .short('l')
+                        .long("lockdown")
+                        .help("Generate the service applying the permanent killswitch at boot instead")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(value_parser!(bool)),
                 ),
         )
         .subcommand(Command::new("uninstall"))
@@ -339,11 +753,18 @@ pub fn init_service_subcommand() -> Command {
 pub fn handle_service_subcommand(args: &ArgMatches) -> Result<()> {
     match args.subcommand() {
         Some(("install", args)) => {
-            let config = service::generate_config()?;
+            let lockdown = matches!(args.get_one::<bool>("lockdown"), Some(true));
+            let config = match lockdown {
+                true => service::generate_lockdown_config()?,
+                false => service::generate_config()?,
+            };
             match args.get_one::<bool>("write") {
                 Some(true) => {
                     let path = args.get_one::<PathBuf>("path");
-                    service::install(&config, path)?;
+                    match lockdown {
+                        true => service::install_lockdown(&config, path)?,
+                        false => service::install(&config, path)?,
+                    }
                 }
                 _ => println!("{}", &config),
             }
@@ -457,12 +878,17 @@ pub fn init_killswitch_subcommand() -> Command {
         .about("Enable/Disable the killswitch")
         .subcommand(Command::new("enable"))
         .subcommand(Command::new("disable"))
+        .subcommand(
+            Command::new("lockdown")
+                .about("Apply the permanent killswitch without the daemon, used at boot"),
+        )
 }
 
 pub fn handle_killswitch_subcommand(args: &ArgMatches) -> Result<()> {
     let enable = match args.subcommand() {
         Some(("enable", _)) => true,
         Some(("disable", _)) => false,
+        Some(("lockdown", _)) => return killswitch::lockdown_from_saved(),
         _ => unimplemented!(),
     };
 
@@ -470,3 +896,133 @@ pub fn handle_killswitch_subcommand(args: &ArgMatches) -> Result<()> {
 
     Ok(())
 }
+
+pub fn init_watch_subcommand() -> Command {
+    Command::new("watch")
+        .visible_alias("w")
+        .about("Print connection events as they happen")
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print every event as a line of json")
+                .action(ArgAction::SetTrue)
+                .value_parser(value_parser!(bool)),
+        )
+}
+
+pub fn handle_watch_subcommand(args: &ArgMatches) -> Result<()> {
+    let json = matches!(args.get_one::<bool>("json"), Some(true));
+
+    for event in daemon::subscribe()? {
+        let event = event?;
+        match json {
+            true => println!("{}", serde_json::to_string(&event)?),
+            false => println!("{} {}", "●".blue(), event),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn init_history_subcommand() -> Command {
+    Command::new("history")
+        .about("List past vpn sessions, oldest first")
+        .arg(
+            Arg::new("server")
+                .short('s')
+                .long("server")
+                .help("Only show sessions for servers matching this name or id"),
+        )
+        .arg(
+            Arg::new("protocol")
+                .short('p')
+                .long("protocol")
+                .help("Only show sessions using this protocol")
+                .value_parser(EnumValueParser::<Protocol>::new()),
+        )
+        .arg(
+            Arg::new("days")
+                .short('d')
+                .long("days")
+                .help("Only show sessions that ended in the last n days")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("limit")
+                .short('n')
+                .long("limit")
+                .help("Only show the n most recent sessions")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print every session as a line of json")
+                .action(ArgAction::SetTrue)
+                .value_parser(value_parser!(bool)),
+        )
+}
+
+pub fn handle_history_subcommand(args: &ArgMatches) -> Result<()> {
+    let now = utils::unix_timestamp(std::time::SystemTime::now());
+    let filter = Filter {
+        server: args.get_one::<String>("server").cloned(),
+        protocol: args.get_one::<Protocol>("protocol").copied(),
+        since: args
+            .get_one::<u64>("days")
+            .map(|days| now.saturating_sub(days * 24 * 60 * 60)),
+        limit: args.get_one::<usize>("limit").copied(),
+    };
+
+    let sessions = match daemon::send_request(Request::History(filter))? {
+        Response::History(sessions) => sessions,
+        res => anyhow::bail!("unexpected response from daemon: {res:?}"),
+    };
+    let json = matches!(args.get_one::<bool>("json"), Some(true));
+
+    for session in sessions {
+        match json {
+            true => println!("{}", serde_json::to_string(&session)?),
+            false => println!(
+                "{} {} {} ({}) for {}, {} in / {} out, {}",
+                "●".blue(),
+                utils::format_timestamp(session.started_at),
+                session.server,
+                session.protocol,
+                utils::format_duration(session.duration()),
+                utils::format_bytes(session.bytes_in),
+                utils::format_bytes(session.bytes_out),
+                session.reason,
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn init_notify_subcommand() -> Command {
+    Command::new("notify")
+        .about("Send desktop notifications for connection events, run this as your own user")
+}
+
+/// Runs until killed, waits for the daemon to come back when it goes away.
+pub fn handle_notify_subcommand(_args: &ArgMatches) -> Result<()> {
+    let config = config::read()?;
+    let mut notifier = Notifier::default();
+
+    loop {
+        if let Ok(events) = daemon::subscribe() {
+            for event in events.map_while(Result::ok) {
+                let Some(notification) = notifier.handle(&event, &config.notifications) else {
+                    continue;
+                };
+
+                if let Err(err) = notification.send() {
+                    eprintln!("{} {err}", "●".red());
+                }
+            }
+        }
+
+        std::thread::sleep(Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::Result;
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddrV4},
+    os::unix::net::UnixStream,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Connection states as reported by openvpn's management interface
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TunnelState {
+    #[default]
+    Connecting,
+    Resolve,
+    TcpConnect,
+    Wait,
+    Auth,
+    GetConfig,
+    AssignIp,
+    AddRoutes,
+    Connected,
+    Reconnecting,
+    Exiting,
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TunnelStatus {
+    pub state: TunnelState,
+    pub tunnel_ip: Option<Ipv4Addr>,
+    pub remote: Option<SocketAddrV4>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub auth_failed: bool,
+    /// Detail of the last reconnect or exit, e.g. `tls-error` or `connection-reset`
+    pub last_error: Option<String>,
+    /// Set once openvpn closes the management socket, usually because it exited
+    pub closed: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message {
+    State {
+        state: TunnelState,
+        detail: String,
+        tunnel_ip: Option<Ipv4Addr>,
+        remote: Option<SocketAddrV4>,
+    },
+    ByteCount {
+        bytes_in: u64,
+        bytes_out: u64,
+    },
+    AuthFailed,
+    Fatal(String),
+}
+
+type OnChange = Box<dyn Fn(&TunnelStatus) + Send>;
+
+/// Client for openvpn's management interface, started with `--management <path> unix`.
+/// A background thread keeps the `TunnelStatus` up to date until openvpn closes the socket.
+#[derive(Debug)]
+pub struct Management {
+    stream: Mutex<UnixStream>,
+    status: Arc<(Mutex<TunnelStatus>, Condvar)>,
+}
+
+impl TunnelState {
+    fn parse(value: &str) -> Self {
+        match value {
+            "CONNECTING" => Self::Connecting,
+            "RESOLVE" => Self::Resolve,
+            "TCP_CONNECT" => Self::TcpConnect,
+            "WAIT" => Self::Wait,
+            "AUTH" => Self::Auth,
+            "GET_CONFIG" => Self::GetConfig,
+            "ASSIGN_IP" => Self::AssignIp,
+            "ADD_ROUTES" => Self::AddRoutes,
+            "CONNECTED" => Self::Connected,
+            "RECONNECTING" => Self::Reconnecting,
+            "EXITING" => Self::Exiting,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for TunnelState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(state) => write!(f, "{state}"),
+            state => write!(f, "{state:?}"),
+        }
+    }
+}
+
+/// Parses a single line sent by the management interface, returns `None` for lines we don't care about.
+/// State lines are accepted both as realtime notifications (`>STATE:...`) and as `state all` history.
+pub fn parse_line(line: &str) -> Option<Message> {
+    let line = line.trim_end();
+
+    if let Some(counts) = line.strip_prefix(">BYTECOUNT:") {
+        let (bytes_in, bytes_out) = counts.split_once(',')?;
+        return Some(Message::ByteCount {
+            bytes_in: bytes_in.parse().ok()?,
+            bytes_out: bytes_out.parse().ok()?,
+        });
+    }
+
+    if let Some(message) = line.strip_prefix(">FATAL:") {
+        return Some(Message::Fatal(message.to_string()));
+    }
+
+    if line.starts_with(">PASSWORD:Verification Failed") {
+        return Some(Message::AuthFailed);
+    }
+
+    let state = line.strip_prefix(">STATE:").unwrap_or(line);
+    // timestamp,state,detail,tunnel ip,remote ip,remote port,...
+    let fields = state.split(',').collect::<Vec<_>>();
+    match fields.as_slice() {
+        [timestamp, state, detail, rest @ ..] if timestamp.parse::<u64>().is_ok() => {
+            let field = |idx: usize| rest.get(idx).filter(|val| !val.is_empty());
+            let tunnel_ip = field(0).and_then(|ip| ip.parse().ok());
+            // An ipv6 or hostname remote is left out, the state change still has to come through
+            let remote = match (field(1), field(2)) {
+                (Some(ip), Some(port)) => ip
+                    .parse()
+                    .ok()
+                    .and_then(|ip| Some(SocketAddrV4::new(ip, port.parse().ok()?))),
+                _ => None,
+            };
+
+            Some(Message::State {
+                state: TunnelState::parse(state),
+                detail: detail.to_string(),
+                tunnel_ip,
+                remote,
+            })
+        }
+        _ => None,
+    }
+}
+
+impl TunnelStatus {
+    /// Applies a message, returns `true` if anything other than the byte counters changed.
+    pub fn apply(&mut self, message: Message) -> bool {
+        match message {
+            Message::State {
+                state,
+                detail,
+                tunnel_ip,
+                remote,
+            } => {
+                if state == TunnelState::Exiting && detail == "auth-failure" {
+                    self.auth_failed = true;
+                }
+                if matches!(state, TunnelState::Reconnecting | TunnelState::Exiting)
+                    && !detail.is_empty()
+                {
+                    self.last_error = Some(detail);
+                }
+                if tunnel_ip.is_some() {
+                    self.tunnel_ip = tunnel_ip;
+                }
+                if remote.is_some() {
+                    self.remote = remote;
+                }
+                self.state = state;
+                true
+            }
+            Message::ByteCount {
+                bytes_in,
+                bytes_out,
+            } => {
+                self.bytes_in = bytes_in;
+                self.bytes_out = bytes_out;
+                false
+            }
+            Message::AuthFailed => {
+                self.auth_failed = true;
+                true
+            }
+            Message::Fatal(message) => {
+                log::error!("OpenVPN fatal error: {message}");
+                false
+            }
+        }
+    }
+}
+
+impl Management {
+    /// Connects to the management socket, retrying until it shows up or `timeout` runs out.
+    /// `on_change` is called from the reader thread whenever the state changes.
+    pub fn connect(
+        path: &Path,
+        timeout: Duration,
+        on_change: impl Fn(&TunnelStatus) + Send + 'static,
+    ) -> Result<Self> {
+        let started = Instant::now();
+        let stream = loop {
+            match UnixStream::connect(path) {
+                Ok(stream) => break stream,
+                Err(err) if started.elapsed() > timeout => {
+                    anyhow::bail!("unable to connect to openvpn management socket {path:?}: {err}")
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(100)),
+            }
+        };
+
+        let status = Arc::new((Mutex::new(TunnelStatus::default()), Condvar::new()));
+        let reader = BufReader::new(stream.try_clone()?);
+        spawn_reader(reader, status.clone(), Box::new(on_change));
+
+        let management = Self {
+            stream: Mutex::new(stream),
+            status,
+        };
+
+        // Replays the state history so we don't miss anything that happened before we connected
+        management.send_command("state on all")?;
+        management.send_command("bytecount 5")?;
+
+        Ok(management)
+    }
+
+    pub fn send_command(&self, command: &str) -> Result<()> {
+        let mut stream = self.stream.lock();
+        stream.write_all(format!("{command}\n").as_bytes())?;
+        stream.flush()?;
+
+        Ok(())
+    }
+
+    pub fn status(&self) -> TunnelStatus {
+        self.status.0.lock().clone()
+    }
+
+    /// Blocks until `predicate` matches the status, returns `None` on timeout.
+    pub fn wait_until(
+        &self,
+        timeout: Duration,
+        predicate: impl Fn(&TunnelStatus) -> bool,
+    ) -> Option<TunnelStatus> {
+        let (lock, condvar) = &*self.status;
+        let deadline = Instant::now() + timeout;
+        let mut status = lock.lock();
+
+        while !predicate(&status) {
+            if condvar.wait_until(&mut status, deadline).timed_out() {
+                return predicate(&status).then(|| status.clone());
+            }
+        }
+
+        Some(status.clone())
+    }
+}
+
+fn spawn_reader(
+    reader: BufReader<UnixStream>,
+    status: Arc<(Mutex<TunnelStatus>, Condvar)>,
+    on_change: OnChange,
+) {
+    std::thread::spawn(move || {
+        let (lock, condvar) = &*status;
+
+        for line in reader.lines().map_while(Result::ok) {
+            log::trace!("openvpn management: {line}");
+
+            let Some(message) = parse_line(&line) else {
+                continue;
+            };
+
+            let mut status = lock.lock();
+            let changed = status.apply(message);
+            let snapshot = status.clone();
+            drop(status);
+            condvar.notify_all();
+
+            if changed {
+                on_change(&snapshot);
+            }
+        }
+
+        let mut status = lock.lock();
+        status.closed = true;
+        let snapshot = status.clone();
+        drop(status);
+        condvar.notify_all();
+
+        log::debug!("OpenVPN closed the management socket");
+        on_change(&snapshot);
+    });
+}
@@ -1,3 +1,4 @@
+pub mod management;
 pub mod openvpn;
 
 use crate::{
@@ -5,9 +6,10 @@ use crate::{
     cache::{self, CachedObject},
 };
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::net::Ipv4Addr;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Pid(u32);
 
 impl Pid {
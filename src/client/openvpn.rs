@@ -9,6 +9,8 @@ use std::{
     io::{BufRead, BufReader, ErrorKind},
 };
 
+use super::management::{Management, TunnelState, TunnelStatus};
+use std::time::{Duration, Instant};
 use sysinfo::Signal;
 
 #[derive(Debug, Clone)]
@@ -37,6 +39,13 @@ impl Protocol {
             Self::Tcp => TCP_PORTS,
         }
     }
+
+    pub fn other(&self) -> Protocol {
+        match self {
+            Self::Udp => Self::Tcp,
+            Self::Tcp => Self::Udp,
+        }
+    }
 }
 
 impl Display for Protocol {
@@ -58,7 +67,43 @@ struct ConfigTemplate {
     update_resolv_conf: Option<String>,
 }
 
-pub fn connect(server: &LogicalServer, protocol: &Protocol) -> Result<Pid> {
+#[derive(Debug)]
+pub enum ConnectError {
+    BinaryMissing,
+    AuthFailed,
+    /// The tunnel didn't come up in time, holds the last known status
+    Timeout(TunnelStatus),
+    /// OpenVPN exited before the tunnel came up, holds the reason found in its log
+    Exited(Option<String>),
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BinaryMissing => write!(f, "`openvpn` was not found, check your PATH."),
+            Self::AuthFailed => write!(f, "authentication failed, check your openvpn credentials"),
+            Self::Timeout(status) => {
+                write!(
+                    f,
+                    "tunnel didn't come up in time, last state: {}",
+                    status.state
+                )?;
+                match status.last_error {
+                    Some(ref error) => write!(f, " ({error})"),
+                    None => Ok(()),
+                }
+            }
+            Self::Exited(Some(reason)) => write!(f, "openvpn exited: {reason}"),
+            Self::Exited(None) => write!(f, "openvpn exited before the tunnel came up"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// Starts openvpn in the background and returns its pid once the pidfile is written,
+/// use `wait_for_tunnel` to find out whether the connection actually succeeds.
+pub fn connect(server: &LogicalServer, protocol: &Protocol, timeout: Duration) -> Result<Pid> {
     let config = config::read()?;
     cache::write::<Config>(&create_config(server, protocol)?)?;
 
@@ -75,8 +120,21 @@ pub fn connect(server: &LogicalServer, protocol: &Protocol) -> Result<Pid> {
         anyhow::bail!("Credentials path does not exist, aborting.");
     }
 
+    let management_path = management_socket_path();
+    if management_path.exists() {
+        std::fs::remove_file(&management_path)?;
+    }
+
+    // A leftover pidfile would make us pick up a pid that doesn't belong to this process
+    let _ = delete_pidfile();
+
     let child = std::process::Command::new("openvpn")
         .arg("--daemon")
+        .args([
+            "--management",
+            management_path.to_str().expect("valid path"),
+            "unix",
+        ])
         .args(["--writepid", PIDFILE_PATH])
         .args([
             "--log",
@@ -96,19 +154,58 @@ pub fn connect(server: &LogicalServer, protocol: &Protocol) -> Result<Pid> {
     let mut child = match child {
         Ok(child) => child,
         Err(e) => match e.kind() {
-            ErrorKind::NotFound => anyhow::bail!("`openvpn` was not found, check your PATH."),
+            ErrorKind::NotFound => return Err(ConnectError::BinaryMissing.into()),
             _ => anyhow::bail!("error connecting with openvpn: {:?}", e),
         },
     };
 
-    child.wait().expect("process to start/finish");
+    // With `--daemon` this only waits for the configuration to be parsed and the process to fork
+    if !child.wait()?.success() {
+        return Err(ConnectError::Exited(read_exit_reason()).into());
+    }
 
-    let pid = utils::wait_for_file_and_read(PIDFILE_PATH)?;
-    let pid = Pid::try_from(pid)?;
+    let pid = match utils::wait_for_file_and_read(PIDFILE_PATH, timeout) {
+        Ok(pid) => Pid::try_from(pid)?,
+        Err(err) => {
+            log::error!("OpenVPN didn't write its pidfile: {err}");
+            return Err(ConnectError::Timeout(TunnelStatus::default()).into());
+        }
+    };
 
     Ok(pid)
 }
 
+/// Blocks until the tunnel is up, openvpn gave up or the `deadline` passed.
+pub fn wait_for_tunnel(management: &Management, deadline: Instant) -> Result<(), ConnectError> {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let status = management.wait_until(timeout, |status| {
+        status.state == TunnelState::Connected
+            || status.state == TunnelState::Exiting
+            || status.auth_failed
+            || status.closed
+    });
+
+    match status {
+        Some(status) if status.auth_failed => Err(ConnectError::AuthFailed),
+        Some(status) if status.state == TunnelState::Connected => Ok(()),
+        Some(_) => Err(ConnectError::Exited(read_exit_reason())),
+        None => Err(ConnectError::Timeout(management.status())),
+    }
+}
+
+pub fn read_exit_reason() -> Option<String> {
+    File::open(cache::get_path().join("ovpn.log"))
+        .ok()
+        .and_then(parse_exit_reason)
+}
+
+/// Name of the tun device openvpn opened, e.g. `tun0`
+pub fn read_nic() -> Option<String> {
+    File::open(cache::get_path().join("ovpn.log"))
+        .ok()
+        .and_then(parse_nic)
+}
+
 pub fn disconnect(pid: &Pid) -> Result<()> {
     utils::kill_process(pid, Signal::Term)?;
 
@@ -189,8 +286,33 @@ pub fn parse_nic(log_file: File) -> Option<String> {
     })
 }
 
+/// Finds the last line in the log explaining why openvpn stopped, if there is one
+pub fn parse_exit_reason(log_file: File) -> Option<String> {
+    let reader = BufReader::new(log_file);
+
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let message = parts.nth(2)?; // skip date and timestamp
+
+            let is_reason = message.contains("AUTH_FAILED")
+                || message.contains("process exiting")
+                || message.contains("Exiting due to fatal error")
+                || message.starts_with("ERROR:");
+
+            is_reason.then(|| message.to_string())
+        })
+        .last()
+}
+
 pub const PIDFILE_PATH: &str = "/etc/protonvpn-rs/openvpn.pid";
 
+pub fn management_socket_path() -> std::path::PathBuf {
+    cache::get_path().join("management.sock")
+}
+
 pub fn read_pidfile() -> Result<Pid> {
     match std::fs::read(PIDFILE_PATH) {
         Ok(content) => Ok(Pid::try_from(String::from_utf8(content)?)?),
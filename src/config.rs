@@ -1,7 +1,14 @@
-use std::{path::PathBuf, str::FromStr, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::Result;
 use clap::ArgMatches;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -10,14 +17,15 @@ use crate::{
     utils,
 };
 
-static CONFIG: OnceLock<Configuration> = OnceLock::new();
+static CONFIG: OnceLock<RwLock<Arc<Configuration>>> = OnceLock::new();
+static CONFIG_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
 static CONFIG_PATHS: [&str; 3] = [
     "/etc/protonvpn-rs/config.ron",
     "~/.config/protonvpn.ron",
     "~/.protonvpn.ron",
 ];
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub enum Select {
     Fastest,
     Random,
@@ -26,7 +34,7 @@ pub enum Select {
 
 // This allows for nicer formatting in the configuration file
 // Serialization of bitflags was problematic when not using json
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub enum FeatureEnum {
     SecureCore,
     Tor,
@@ -35,7 +43,7 @@ pub enum FeatureEnum {
     Ipv6,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Filters {
     pub tier: Tier,
     pub max_load: u8,
@@ -46,29 +54,196 @@ pub struct Filters {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Killswitch {
     pub enable: bool,
+    /// Written in the syntax of the firewall backend that's used
     pub custom_rules: Option<Vec<String>>,
+    /// Only used on linux
+    #[serde(default)]
+    pub backend: FirewallBackend,
+    /// Keeps blocking traffic while disconnected, after the daemon stopped and from boot on.
+    /// Only supported on linux
+    #[serde(default)]
+    pub permanent: bool,
+    /// Resolvers that stay reachable while the permanent killswitch blocks everything else
+    #[serde(default)]
+    pub dns: Vec<Ipv4Addr>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FirewallBackend {
+    /// nftables if `nft` is installed and `iptables` is missing or uses nftables itself
+    #[default]
+    Auto,
+    Iptables,
+    Nftables,
+}
+
+/// What the daemon does when the openvpn process exits without being asked to
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Reconnect {
+    pub enable: bool,
+    pub max_retries: u32,
+    /// Seconds to wait before the first retry, doubled after every failed attempt
+    pub backoff: u64,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            max_retries: 3,
+            backoff: 5,
+        }
+    }
+}
+
+/// Which server the daemon connects to when it starts, a resumed connection takes precedence
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum Startup {
+    None,
+    /// The best server matching `default_criteria`, selected by `default_select`
+    DefaultCriteria,
+    /// The server the daemon was last connected to
+    LastUsed,
+    /// A profile from `profiles` by name
+    Profile(String),
+}
+
+/// Named set of criteria, e.g. to connect to a specific country on startup
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Profile {
+    pub criteria: Filters,
+    /// Falls back to `default_select`
+    pub select: Option<Select>,
+    /// Falls back to `default_protocol`
+    pub protocol: Option<Protocol>,
+}
+
+/// What the daemon does with the connection it had before it was restarted
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resume {
+    /// Start from scratch, stopping any openvpn process that's still running
+    Never,
+    /// Take over the openvpn process if it's still running, reconnect otherwise.
+    /// The tunnel is left running when the daemon is stopped.
+    Adopt,
+    /// Reconnect to the same server with the same protocol
+    #[default]
+    Reconnect,
+}
+
+/// Which events `protonvpn-rs notify` sends desktop notifications for
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Notifications {
+    pub connected: bool,
+    pub disconnected: bool,
+    /// The openvpn process exited without being asked to
+    pub dropped: bool,
+    pub killswitch: bool,
+    pub auth_failed: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            connected: true,
+            disconnected: true,
+            dropped: true,
+            killswitch: true,
+            auth_failed: true,
+        }
+    }
+}
+
+/// Shell commands the daemon runs on connection changes, see `hooks::HookEnv` for the variables they get
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre_connect: Vec<String>,
+    pub post_connect: Vec<String>,
+    pub pre_disconnect: Vec<String>,
+    pub post_disconnect: Vec<String>,
+    pub on_failure: Vec<String>,
+    pub on_killswitch_change: Vec<String>,
+    /// Seconds a command may run before it's killed
+    pub timeout: u64,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            pre_connect: Vec::new(),
+            post_connect: Vec::new(),
+            pre_disconnect: Vec::new(),
+            post_disconnect: Vec::new(),
+            on_failure: Vec::new(),
+            on_killswitch_change: Vec::new(),
+            timeout: 10,
+        }
+    }
+}
+
+/// How connect requests fall back to other servers when connecting fails
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Failover {
+    /// Maximum amount of servers to try for a single connect request
+    pub max_servers: usize,
+    /// Also try the other protocol before moving on to the next server
+    pub try_other_protocol: bool,
+}
+
+impl Default for Failover {
+    fn default() -> Self {
+        Self {
+            max_servers: 3,
+            try_other_protocol: false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(default)]
 pub struct Configuration {
     pub max_cache_age: u64,
+    /// Minutes between server list refreshes in the daemon, `0` disables them
+    pub server_refresh_interval: u64,
+    /// Superseded by `startup`, only used when `startup` isn't set
     pub autostart_default: bool,
+    pub startup: Option<Startup>,
+    pub profiles: HashMap<String, Profile>,
     pub default_select: Select,
     pub default_criteria: Filters,
     pub default_protocol: Protocol,
     pub credentials_path: Option<PathBuf>,
+    /// Group that owns the daemon's socket, its members can use the cli without root
+    pub socket_group: Option<String>,
+    /// Seconds to wait for the tunnel to come up before a connection attempt is aborted
+    pub connect_timeout: u64,
     #[cfg(target_os = "linux")]
     pub update_resolv_conf_path: Option<PathBuf>,
     pub killswitch: Killswitch,
+    /// Disables ipv6 on every interface except the tunnel while connected, linux only
+    pub disable_ipv6: bool,
+    pub reconnect: Reconnect,
+    pub failover: Failover,
+    pub resume: Resume,
+    pub notifications: Notifications,
+    pub hooks: Hooks,
 }
 
 impl Default for Configuration {
     fn default() -> Self {
         Self {
             max_cache_age: 3,
+            server_refresh_interval: 60,
             autostart_default: false,
+            startup: None,
+            profiles: HashMap::new(),
             credentials_path: None,
+            socket_group: None,
+            connect_timeout: 30,
             #[cfg(target_os = "linux")]
             update_resolv_conf_path: None,
             default_select: Select::Fastest,
@@ -82,23 +257,95 @@ impl Default for Configuration {
             killswitch: Killswitch {
                 enable: false,
                 custom_rules: None,
+                backend: FirewallBackend::default(),
+                permanent: false,
+                dns: Vec::new(),
             },
+            disable_ipv6: false,
+            reconnect: Reconnect::default(),
+            failover: Failover::default(),
+            resume: Resume::default(),
+            notifications: Notifications::default(),
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+impl Configuration {
+    pub fn startup(&self) -> Startup {
+        match (&self.startup, self.autostart_default) {
+            (Some(startup), _) => startup.to_owned(),
+            (None, true) => Startup::DefaultCriteria,
+            (None, false) => Startup::None,
+        }
+    }
+}
+
+impl Configuration {
+    /// Catches mistakes serde can't, an invalid configuration is rejected as a whole
+    pub fn validate(&self) -> Result<()> {
+        if self.connect_timeout == 0 {
+            anyhow::bail!("connect_timeout has to be at least 1 second");
+        }
+        if self.hooks.timeout == 0 {
+            anyhow::bail!("hooks.timeout has to be at least 1 second");
+        }
+        if self.default_criteria.max_load > 100 {
+            anyhow::bail!("default_criteria.max_load can't be more than 100");
+        }
+
+        for (name, profile) in self.profiles.iter() {
+            if profile.criteria.max_load > 100 {
+                anyhow::bail!("profiles.{name}.criteria.max_load can't be more than 100");
+            }
         }
+
+        if let Some(Startup::Profile(ref name)) = self.startup {
+            if !self.profiles.contains_key(name) {
+                anyhow::bail!("startup profile {name} doesn't exist");
+            }
+        }
+
+        if let Some(ref rules) = self.killswitch.custom_rules {
+            if rules.iter().any(|rule| rule.trim().is_empty()) {
+                anyhow::bail!("killswitch.custom_rules can't contain empty rules");
+            }
+        }
+
+        if self.killswitch.permanent && cfg!(not(target_os = "linux")) {
+            anyhow::bail!("killswitch.permanent is only supported on linux");
+        }
+        // Nothing else would be able to resolve while disconnected
+        if self.killswitch.permanent && self.killswitch.dns.is_empty() {
+            anyhow::bail!("killswitch.permanent requires at least one resolver in killswitch.dns");
+        }
+
+        Ok(())
     }
 }
 
 fn parse_from_path(path: &PathBuf) -> Result<Configuration> {
-    Ok(match std::fs::read_to_string(path) {
+    let config = match std::fs::read_to_string(path) {
         Ok(content) => ron::from_str::<Configuration>(&content)?,
         Err(_) => Configuration::default(),
-    })
+    };
+    config.validate()?;
+
+    Ok(config)
+}
+
+fn set(config: Configuration, path: Option<PathBuf>) {
+    CONFIG
+        .set(RwLock::new(Arc::new(config)))
+        .expect("OnceLock to be unlocked");
+    CONFIG_PATH.set(path).expect("OnceLock to be unlocked");
 }
 
 pub fn init(args: &ArgMatches) -> Result<()> {
     match args.get_one::<PathBuf>("config") {
         Some(path) => {
             let data = parse_from_path(path)?;
-            CONFIG.set(data).expect("OnceLock to be unlocked");
+            set(data, Some(path.to_owned()));
         }
         None => {
             for path in CONFIG_PATHS.iter() {
@@ -112,28 +359,50 @@ pub fn init(args: &ArgMatches) -> Result<()> {
                 }
 
                 let data = parse_from_path(&path)?;
-                CONFIG.set(data).expect("OnceLock to be unlocked");
+                set(data, Some(path));
                 break;
             }
         }
     };
 
     if CONFIG.get().is_none() {
-        CONFIG
-            .set(Configuration::default())
-            .expect("OnceLock to be unlocked");
+        set(Configuration::default(), None);
     }
 
     Ok(())
 }
 
-pub fn read() -> Result<&'static Configuration> {
+/// Returns a snapshot, hold on to it for the duration of an operation so a reload can't change values halfway.
+pub fn read() -> Result<Arc<Configuration>> {
     match CONFIG.get() {
-        Some(value) => Ok(value),
+        Some(value) => Ok(value.read().clone()),
         None => panic!("config read() called before init()!"),
     }
 }
 
+/// The file the configuration was loaded from, `None` when the defaults are used
+pub fn path() -> Option<&'static PathBuf> {
+    CONFIG_PATH.get().and_then(|path| path.as_ref())
+}
+
+/// Reads the configuration file again and replaces the current configuration, returns the previous one.
+/// The current configuration is kept when the file can't be read or is invalid.
+pub fn reload() -> Result<Arc<Configuration>> {
+    let Some(path) = path() else {
+        anyhow::bail!("no configuration file was loaded on startup");
+    };
+
+    let config = ron::from_str::<Configuration>(&std::fs::read_to_string(path)?)?;
+    config.validate()?;
+
+    let mut current = CONFIG
+        .get()
+        .expect("config reload() called before init()!")
+        .write();
+
+    Ok(std::mem::replace(&mut *current, Arc::new(config)))
+}
+
 pub trait FlattenBitflagEnum<F> {
     fn flatten(&self) -> F;
 }
@@ -0,0 +1,1290 @@
+use crate::{
+    api::{self, types::LogicalServer, LogicalServers, Ordering},
+    cache,
+    client::{
+        self,
+        management::{Management, TunnelStatus},
+        openvpn::{self, ConnectError, Protocol},
+        Pid,
+    },
+    config::{self, Filters, Resume, Startup},
+    history::{self, Session},
+    hooks::{self, Hook, HookEnv},
+    ipv6, killswitch,
+    protocol::{
+        self, ConnectionInfo, DaemonError, ErrorCode, Event, FailedAttempt, Request, Response,
+        ServerId, ServerStatus, SocketProtocol, MAX_FRAME_LEN, PROTOCOL_VERSION,
+    },
+    utils,
+};
+use anyhow::Result;
+use log;
+use parking_lot::{Mutex, RwLock};
+use persist::{LastUsed, PersistedConnection};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{
+    fs::Permissions,
+    net::IpAddr,
+    os::unix::fs::PermissionsExt,
+    os::unix::net::{UnixListener, UnixStream},
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use sysinfo::Signal;
+
+pub mod persist;
+mod refresh;
+mod reload;
+mod supervisor;
+
+const MANAGEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Events queued for a subscriber that doesn't keep up, it's dropped once the queue is full
+const SUBSCRIBER_QUEUE: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct ActiveServer {
+    pub pid: Pid,
+    pub server: LogicalServer,
+    pub protocol: Protocol,
+    pub management: Option<Arc<Management>>,
+    pub connected_at: SystemTime,
+}
+
+/// A connection that has been started but isn't up yet
+#[derive(Debug)]
+pub struct PendingConnection {
+    pub server: LogicalServer,
+    pub protocol: Protocol,
+    /// `None` until openvpn has written its pidfile
+    pub pid: Option<Pid>,
+    cancelled: bool,
+}
+
+pub type DaemonState = Arc<State>;
+pub struct State {
+    /// Replaced as a whole when the server list is refreshed
+    pub servers: RwLock<LogicalServers>,
+    pub active_server: RwLock<Option<ActiveServer>>,
+    pub pending: Mutex<Option<PendingConnection>>,
+    /// Why the last openvpn process exited on its own, cleared on the next successful connect
+    pub last_exit: RwLock<Option<String>>,
+    pub killswitch_enabled: RwLock<bool>,
+    pub subscribers: Mutex<Vec<SyncSender<Event>>>,
+    /// Held by requests that start or stop openvpn so they can't interleave,
+    /// other requests are answered while a connection is being established.
+    pub connection_lock: Mutex<()>,
+}
+
+impl State {
+    /// Looks up a server in the current server list
+    pub fn server(&self, id: &str) -> Option<LogicalServer> {
+        self.servers
+            .read()
+            .iter()
+            .find(|server| server.id == id)
+            .cloned()
+    }
+}
+
+pub fn start_service() -> Result<()> {
+    pretty_env_logger::init();
+    log::info!("Starting daemon");
+
+    let config = config::read()?;
+    let servers = api::logicals()?;
+
+    let state = Arc::new(State {
+        servers: RwLock::new(servers.clone()),
+        active_server: RwLock::new(None),
+        pending: Mutex::new(None),
+        last_exit: RwLock::new(None),
+        killswitch_enabled: RwLock::new(false),
+        subscribers: Mutex::new(Vec::new()),
+        connection_lock: Mutex::new(()),
+    });
+
+    let persisted = persist::read().unwrap_or_default();
+    let previous = match config.resume {
+        Resume::Never => None,
+        _ => persisted.connection.as_ref(),
+    };
+
+    let adopted = match (config.resume, previous) {
+        (Resume::Adopt, Some(connection)) => match adopt_connection(&state, connection) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!("Unable to adopt openvpn process {}: {err}", connection.pid);
+                false
+            }
+        },
+        _ => false,
+    };
+
+    // Applied after adopting so the rules allow the tunnel that's still running,
+    // but before reconnecting so nothing leaks while the tunnel comes up
+    if config.killswitch.enable || config.killswitch.permanent {
+        if let Err(err) = handle_killswitch_request(&state, &true) {
+            anyhow::bail!("Error trying to enable killswitch, aborting. {err}");
+        }
+    }
+
+    if !adopted {
+        // Left behind if the daemon didn't get to disconnect
+        restore_ipv6();
+
+        if let Ok(pid) = openvpn::read_pidfile() {
+            log::debug!("Found leftover openvpn pid file, attempting cleanup");
+
+            match utils::kill_process(&pid, Signal::Term) {
+                Ok(()) => log::debug!("Succesfully killed orphan process"),
+                Err(err) => log::error!("Unable to cleanup orphan process, error: {err}"),
+            }
+        };
+    }
+
+    spawn_signal_handler(&state)?;
+    reload::spawn(&state)?;
+    refresh::spawn(&state);
+
+    log::info!("Daemon initialized");
+
+    let resumed = match previous {
+        _ if adopted => true,
+        Some(connection) => {
+            log::info!("Reconnecting to {} after restart", connection.server);
+            let candidates = [connection.server.to_owned()];
+            match handle_connect_request(&candidates, &connection.protocol, &state) {
+                Ok(_) => true,
+                Err(err) => {
+                    log::error!("Unable to restore previous connection: {err}");
+                    false
+                }
+            }
+        }
+        None => false,
+    };
+
+    let restore_killswitch = config.resume != Resume::Never && persisted.killswitch;
+    if restore_killswitch && !*state.killswitch_enabled.read() {
+        if let Err(err) = handle_killswitch_request(&state, &true) {
+            log::error!("Unable to restore killswitch: {err}");
+        }
+    }
+
+    let startup = match resumed {
+        true => {
+            log::info!("Restored previous connection, skipping startup policy");
+            None
+        }
+        false => startup_connection(&servers, persisted.last_used.as_ref()),
+    };
+
+    if let Some((candidates, protocol)) = startup {
+        if let Err(err) = handle_connect_request(&candidates, &protocol, &state) {
+            log::error!("Error while trying to connect on startup: {}", err)
+        }
+    }
+
+    let stream = bind_socket()?;
+
+    for client in stream.incoming() {
+        let mut client = match client {
+            Ok(client) => client,
+            Err(err) => {
+                log::error!("Unable to accept client: {err}");
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_client(&mut client, &state) {
+                log::error!("Error while handling client: {:?}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Resolves `config.startup()` into the servers to connect to,
+/// returns `None` if the daemon should stay disconnected.
+fn startup_connection(
+    servers: &LogicalServers,
+    last_used: Option<&LastUsed>,
+) -> Option<(Vec<ServerId>, Protocol)> {
+    let config = config::read().expect("config to be initialized");
+    let startup = config.startup();
+
+    let (candidates, protocol) = match startup {
+        Startup::None => {
+            log::info!("Startup policy is None, staying disconnected");
+            return None;
+        }
+        Startup::DefaultCriteria => {
+            let candidates = servers
+                .to_filtered(&config.default_criteria)
+                .rank(&config.default_select);
+            (candidates, config.default_protocol)
+        }
+        Startup::LastUsed => match last_used {
+            Some(last_used) => {
+                let candidates = servers
+                    .iter()
+                    .filter(|server| server.id == last_used.server)
+                    .collect();
+                (candidates, last_used.protocol)
+            }
+            None => {
+                log::info!("Startup policy is LastUsed but no server was used before, staying disconnected");
+                return None;
+            }
+        },
+        Startup::Profile(ref name) => match config.profiles.get(name) {
+            Some(profile) => {
+                let select = profile.select.as_ref().unwrap_or(&config.default_select);
+                let candidates = servers.to_filtered(&profile.criteria).rank(select);
+                (
+                    candidates,
+                    profile.protocol.unwrap_or(config.default_protocol),
+                )
+            }
+            None => {
+                log::error!("Startup profile {name} doesn't exist, staying disconnected");
+                return None;
+            }
+        },
+    };
+
+    match candidates.first() {
+        Some(server) => {
+            log::info!(
+                "Startup policy is {startup:?}, connecting to {} ({protocol})",
+                server.name
+            );
+            let candidates = candidates
+                .iter()
+                .map(|server| server.id.to_owned())
+                .collect();
+            Some((candidates, protocol))
+        }
+        None => {
+            log::warn!("Startup policy is {startup:?} but no servers match, staying disconnected");
+            None
+        }
+    }
+}
+
+/// Performs the version handshake, then answers every request until the client hangs up.
+fn handle_client(stream: &mut UnixStream, state: &DaemonState) -> Result<()> {
+    if !handshake(stream)? {
+        return Ok(());
+    }
+
+    loop {
+        // After a framing error the position in the stream is unknown, the next length prefix can't be trusted
+        let frame = match protocol::read_frame(stream) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                log::warn!("Unable to read request frame, closing connection: {err}");
+                let res = DaemonError::new(ErrorCode::MalformedRequest, err.to_string());
+                let _ = Response::from(res).write_to(stream);
+                return Ok(());
+            }
+        };
+
+        // The whole frame was read, the next one can still be decoded
+        let req = match Request::deserialize(&frame) {
+            Ok(req) => req,
+            Err(err) => {
+                log::warn!("Received malformed request: {err}");
+                let res = DaemonError::new(ErrorCode::MalformedRequest, err.to_string());
+                Response::from(res).write_to(stream)?;
+                continue;
+            }
+        };
+
+        log::trace!(req:?; "Incoming request");
+
+        if req == Request::Subscribe {
+            Response::Ok.write_to(stream)?;
+            return add_subscriber(stream, state);
+        }
+
+        let res = match handle_socket_request(&req, state) {
+            Ok(res) => {
+                log::info!("Succesfully processed instruction {:?}", req);
+                res
+            }
+            Err(err) => {
+                log::error!("Error handling instruction: {:?}", err);
+                broadcast(state, Event::Error(err.to_string()));
+                daemon_error(err).into()
+            }
+        };
+
+        // Dropping the connection would leave the client with nothing but an EOF
+        let data = res.serialize();
+        if data.len() > MAX_FRAME_LEN as usize {
+            log::error!("Response of {} bytes is too large to send", data.len());
+            let res = DaemonError::new(
+                ErrorCode::Internal,
+                format!(
+                    "response of {} bytes exceeds the maximum of {MAX_FRAME_LEN} bytes, try limiting the results",
+                    data.len()
+                ),
+            );
+            Response::from(res).write_to(stream)?;
+            continue;
+        }
+
+        protocol::write_frame(stream, &data)?;
+    }
+}
+
+fn daemon_error(err: anyhow::Error) -> DaemonError {
+    match err.downcast::<DaemonError>() {
+        Ok(err) => err,
+        Err(err) => match err.downcast::<ConnectError>() {
+            Ok(err) => err.into(),
+            Err(err) => DaemonError::new(ErrorCode::Internal, err.to_string()),
+        },
+    }
+}
+
+/// Returns `false` if the client should be disconnected
+fn handshake(stream: &mut UnixStream) -> Result<bool> {
+    let frame = match protocol::read_frame(stream) {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return Ok(false),
+        Err(err) => {
+            log::warn!("Unable to read handshake frame, closing connection: {err}");
+            let res = DaemonError::new(ErrorCode::MalformedRequest, err.to_string());
+            let _ = Response::from(res).write_to(stream);
+            return Ok(false);
+        }
+    };
+
+    let res = match Request::deserialize(&frame) {
+        Ok(Request::Hello { version }) if version == PROTOCOL_VERSION => {
+            Response::Hello {
+                version: PROTOCOL_VERSION,
+            }
+            .write_to(stream)?;
+            return Ok(true);
+        }
+        Ok(Request::Hello { version }) => DaemonError::new(
+            ErrorCode::VersionMismatch,
+            format!("client uses protocol version {version}, daemon expects {PROTOCOL_VERSION}"),
+        ),
+        Ok(_) => DaemonError::new(
+            ErrorCode::HandshakeRequired,
+            "first message has to be a hello",
+        ),
+        Err(err) => DaemonError::new(ErrorCode::MalformedRequest, err.to_string()),
+    };
+
+    log::warn!("Rejected client during handshake: {res}");
+    Response::from(res).write_to(stream)?;
+
+    Ok(false)
+}
+
+fn handle_socket_request(req: &Request, state: &DaemonState) -> Result<Response> {
+    match req {
+        Request::Hello { .. } => {
+            return Ok(Response::Hello {
+                version: PROTOCOL_VERSION,
+            })
+        }
+        Request::Status => return Ok(handle_status_request(state)),
+        Request::Disconnect => handle_disconnect_request(state)?,
+        Request::Connect(candidates, protocol) => {
+            return handle_connect_request(candidates, protocol, state)
+        }
+        Request::ConnectMatching(filters, select, protocol) => {
+            let candidates = state
+                .servers
+                .read()
+                .to_filtered(filters)
+                .rank(select)
+                .iter()
+                .map(|server| server.id.to_owned())
+                .collect::<Vec<_>>();
+
+            if candidates.is_empty() {
+                return Err(DaemonError::new(
+                    ErrorCode::ServerNotFound,
+                    "No servers matching search criteria",
+                )
+                .into());
+            }
+
+            return handle_connect_request(&candidates, protocol, state);
+        }
+        Request::Query(filters, ordering, limit) => {
+            return Ok(handle_query_request(state, filters, ordering, limit))
+        }
+        Request::Killswitch(enable) => handle_killswitch_request(state, enable)?,
+        Request::RefreshServers => {
+            let servers = refresh::refresh(state)?;
+            return Ok(Response::Refreshed { servers });
+        }
+        Request::History(filter) => {
+            let sessions = history::read_from(&history::path())?;
+            return Ok(Response::History(history::filter(sessions, filter)));
+        }
+        Request::Subscribe => unreachable!("subscriptions are handled by `handle_client`"),
+    }
+
+    Ok(Response::Ok)
+}
+
+fn handle_query_request(
+    state: &DaemonState,
+    filters: &Filters,
+    ordering: &Option<Ordering>,
+    limit: &Option<usize>,
+) -> Response {
+    let servers = state.servers.read().clone();
+    let mut filtered = servers.to_filtered(filters);
+    if let Some(ordering) = ordering {
+        filtered = filtered.sort_by(ordering);
+    }
+
+    let servers = filtered
+        .0
+        .into_iter()
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect();
+
+    Response::Servers(servers)
+}
+
+/// Keeps a handle to the client's stream around so `broadcast` can push events to it.
+/// Writes events to the subscriber from the client's own thread until it hangs up,
+/// so a subscriber that reads slowly only holds up itself.
+fn add_subscriber(stream: &mut UnixStream, state: &DaemonState) -> Result<()> {
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+    let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_QUEUE);
+    state.subscribers.lock().push(tx);
+    log::debug!("Added event subscriber");
+
+    // Ends once `broadcast` dropped the sender
+    for event in rx {
+        if let Err(err) = Response::Event(event).write_to(stream) {
+            log::debug!("Event subscriber hung up: {err}");
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Queues an event for all subscribers without waiting for them, dropping the ones
+/// that have hung up or stopped reading.
+pub fn broadcast(state: &DaemonState, event: Event) {
+    log::trace!(event:?; "Broadcasting event");
+
+    state
+        .subscribers
+        .lock()
+        .retain(|subscriber| match subscriber.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                log::debug!("Dropping event subscriber that stopped reading");
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                log::debug!("Dropping event subscriber that hung up");
+                false
+            }
+        });
+}
+
+fn handle_status_request(state: &DaemonState) -> Response {
+    match state.active_server.read().clone() {
+        Some(active) => Response::Status(ServerStatus::Connected(connection_info(state, active))),
+        None => match *state.pending.lock() {
+            Some(ref pending) => Response::Status(ServerStatus::Connecting {
+                name: pending.server.name.to_owned(),
+                protocol: pending.protocol.to_owned(),
+            }),
+            None => Response::Status(ServerStatus::Disconnected {
+                last_exit: state.last_exit.read().clone(),
+            }),
+        },
+    }
+}
+
+fn connection_info(state: &DaemonState, active: ActiveServer) -> ConnectionInfo {
+    let status = active
+        .management
+        .as_ref()
+        .map(|management| management.status());
+    let interface = openvpn::read_nic().and_then(|name| utils::find_nic(&name));
+
+    // Fall back to the interface's address if the management interface isn't available
+    let tunnel_ip = match status {
+        Some(ref status) => status.tunnel_ip,
+        None => interface.as_ref().and_then(|interface| {
+            interface.ips.iter().find_map(|ip| match ip.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                IpAddr::V6(_) => None,
+            })
+        }),
+    };
+    let status = status.unwrap_or_default();
+    // The server list is more recent than the copy taken when connecting, e.g. after adopting
+    let server = state.server(&active.server.id).unwrap_or(active.server);
+
+    ConnectionInfo {
+        name: server.name,
+        pid: active.pid,
+        protocol: active.protocol,
+        state: active
+            .management
+            .is_some()
+            .then_some(status.state.to_owned()),
+        connected_since: utils::unix_timestamp(active.connected_at),
+        interface: interface.map(|interface| interface.name),
+        tunnel_ip,
+        remote: status.remote,
+        bytes_in: status.bytes_in,
+        bytes_out: status.bytes_out,
+        killswitch: *state.killswitch_enabled.read(),
+        country: server.exit_country,
+        load: server.load,
+        features: server.features,
+    }
+}
+
+fn handle_disconnect_request(state: &DaemonState) -> Result<()> {
+    cancel_pending(state);
+    let _guard = state.connection_lock.lock();
+    let active = match state.active_server.read().clone() {
+        Some(active) => active,
+        _ => {
+            log::debug!("No currently running vpn client, doing nothing.");
+            return Ok(());
+        }
+    };
+
+    let env = HookEnv::server(&active.server, &active.protocol).reason("disconnected");
+    hooks::run_blocking(Hook::PreDisconnect, &env);
+
+    client::openvpn::disconnect(&active.pid)?;
+    record_session(&active, "disconnected");
+    broadcast(
+        state,
+        Event::OpenvpnExited {
+            pid: active.pid,
+            reason: None,
+            unexpected: false,
+        },
+    );
+
+    let mut active_server = state.active_server.write();
+    *active_server = None;
+    drop(active_server);
+    restore_ipv6();
+    persist::save(state);
+
+    broadcast(state, Event::Disconnected);
+    hooks::run(Hook::PostDisconnect, &env);
+
+    Ok(())
+}
+
+/// Tries the candidates in order until one of them connects, a candidate is skipped when connecting
+/// times out or openvpn exits. Errors that another server won't fix, like bad credentials, abort right away.
+fn handle_connect_request(
+    candidates: &[ServerId],
+    protocol: &Protocol,
+    state: &DaemonState,
+) -> Result<Response> {
+    cancel_pending(state);
+    let _guard = state.connection_lock.lock();
+    let config = config::read()?;
+
+    let mut protocols = vec![*protocol];
+    if config.failover.try_other_protocol {
+        protocols.push(protocol.other());
+    }
+
+    let mut failed = Vec::new();
+    let mut last_err = None;
+    let mut last_attempt = None;
+    let mut aborted = false;
+
+    'candidates: for server_id in candidates.iter().take(config.failover.max_servers.max(1)) {
+        for protocol in protocols.iter() {
+            last_attempt = Some((server_id, *protocol));
+            let err = match connect_to_server(server_id, protocol, state) {
+                Ok(server) => {
+                    run_post_connect_hook(state);
+                    return Ok(Response::Connected {
+                        server,
+                        protocol: *protocol,
+                        failed,
+                    });
+                }
+                Err(err) => daemon_error(err),
+            };
+
+            // Anything else, like missing credentials, would fail the same way for every candidate
+            let retry = matches!(
+                err.code,
+                ErrorCode::ServerNotFound | ErrorCode::ConnectTimeout | ErrorCode::ConnectFailed
+            );
+            if err.code == ErrorCode::AuthFailed {
+                let server = match state.server(server_id) {
+                    Some(server) => server.name,
+                    None => server_id.to_owned(),
+                };
+                broadcast(state, Event::AuthFailed { server });
+            }
+            if !retry {
+                last_err = Some(err);
+                aborted = true;
+                break 'candidates;
+            }
+
+            let server = match state.server(server_id) {
+                Some(server) => server.name,
+                None => server_id.to_owned(),
+            };
+            log::warn!(
+                "Connecting to {server} ({protocol}) failed: {}",
+                err.message
+            );
+            broadcast(
+                state,
+                Event::Error(format!("{server} ({protocol}): {}", err.message)),
+            );
+
+            failed.push(FailedAttempt {
+                server,
+                protocol: *protocol,
+                error: err.message.to_owned(),
+            });
+            last_err = Some(err);
+        }
+    }
+
+    let reason = last_err.as_ref().map(|err| err.message.to_owned());
+    let last_server =
+        last_attempt.and_then(|(server_id, protocol)| Some((state.server(server_id)?, protocol)));
+    let env = match last_server {
+        Some((server, protocol)) => HookEnv {
+            reason,
+            ..HookEnv::server(&server, &protocol)
+        },
+        None => HookEnv {
+            reason,
+            protocol: Some(*protocol),
+            ..Default::default()
+        },
+    };
+    hooks::run(Hook::OnFailure, &env);
+
+    match last_err {
+        Some(err) if !aborted && failed.len() > 1 => Err(DaemonError::new(
+            err.code,
+            format!(
+                "All {} connection attempts failed, last error: {}",
+                failed.len(),
+                err.message
+            ),
+        )
+        .into()),
+        Some(err) => Err(err.into()),
+        None => Err(DaemonError::new(ErrorCode::ServerNotFound, "No servers to connect to").into()),
+    }
+}
+
+fn run_post_connect_hook(state: &DaemonState) {
+    let Some(active) = state.active_server.read().clone() else {
+        return;
+    };
+
+    let info = connection_info(state, active.to_owned());
+    let env = HookEnv {
+        interface: info.interface,
+        tunnel_ip: info.tunnel_ip,
+        ..HookEnv::server(&active.server, &active.protocol)
+    };
+    hooks::run(Hook::PostConnect, &env);
+}
+
+/// Returns the name of the server once connected
+fn connect_to_server(server_id: &str, protocol: &Protocol, state: &DaemonState) -> Result<String> {
+    let logical_server = match state.server(server_id) {
+        Some(server) => server,
+        None => {
+            return Err(DaemonError::new(
+                ErrorCode::ServerNotFound,
+                format!("No server found with id: {server_id}"),
+            )
+            .into())
+        }
+    };
+
+    let previous = state.active_server.read().clone();
+    if let Some(ref active) = previous {
+        if server_id == active.server.id && protocol == &active.protocol {
+            log::debug!("Same server and same protocol, doing nothing.");
+            return Ok(active.server.name.to_owned());
+        }
+    }
+
+    // Runs while the previous tunnel is still up so it can act before the switch
+    hooks::run_blocking(
+        Hook::PreConnect,
+        &HookEnv::server(&logical_server, protocol),
+    );
+
+    if let Some(active) = previous {
+        utils::kill_process(&active.pid, Signal::Term)?;
+        *state.active_server.write() = None;
+        restore_ipv6();
+        persist::save(state);
+        record_session(&active, "switched server");
+        broadcast(
+            state,
+            Event::OpenvpnExited {
+                pid: active.pid,
+                reason: None,
+                unexpected: false,
+            },
+        );
+    }
+
+    // The killswitch only lets traffic through to the entry ips of the server it was enabled for
+    if *state.killswitch_enabled.read() {
+        log::debug!("Reapplying killswitch rules for {}", logical_server.name);
+        killswitch::allow_server(protocol, &logical_server.entry_ips())?;
+    }
+
+    log::info!("Connecting to server {}", logical_server.name);
+    broadcast(
+        state,
+        Event::Connecting {
+            server: logical_server.name.to_owned(),
+            protocol: *protocol,
+        },
+    );
+    let active = start_openvpn(state, &logical_server, protocol)?;
+    let pid = active.pid.to_owned();
+
+    log::info!("Connected to {:?}", active);
+    *state.active_server.write() = Some(active);
+    *state.last_exit.write() = None;
+
+    // The tunnel device is only known for sure now, e.g. after the permanent killswitch was applied while disconnected
+    if *state.killswitch_enabled.read() {
+        if let Err(err) = killswitch::enable(protocol, &logical_server.entry_ips()) {
+            log::error!("Unable to allow the tunnel in the killswitch: {err}");
+        }
+    }
+
+    protect_ipv6();
+    persist::save(state);
+    supervisor::spawn(state, pid);
+
+    broadcast(
+        state,
+        Event::Connected {
+            server: logical_server.name.to_owned(),
+            protocol: *protocol,
+        },
+    );
+
+    Ok(logical_server.name.to_owned())
+}
+
+/// Starts openvpn and blocks until the tunnel is up or `connect_timeout` runs out,
+/// the process is stopped again when the connection fails.
+fn start_openvpn(
+    state: &DaemonState,
+    server: &LogicalServer,
+    protocol: &Protocol,
+) -> Result<ActiveServer> {
+    let config = config::read()?;
+    let deadline = Instant::now() + Duration::from_secs(config.connect_timeout);
+
+    *state.pending.lock() = Some(PendingConnection {
+        server: server.to_owned(),
+        protocol: protocol.to_owned(),
+        pid: None,
+        cancelled: false,
+    });
+
+    let pid = match openvpn::connect(
+        server,
+        protocol,
+        Duration::from_secs(config.connect_timeout),
+    ) {
+        Ok(pid) => pid,
+        Err(err) => {
+            state.pending.lock().take();
+            return Err(err);
+        }
+    };
+
+    let management = set_pending_pid(state, &pid).and_then(|_| {
+        let management = attach_management(state)?;
+        openvpn::wait_for_tunnel(&management, deadline)?;
+        Ok(management)
+    });
+
+    // If the connection was cancelled while we waited the process is already gone
+    let cancelled = match state.pending.lock().take() {
+        Some(pending) => pending.cancelled,
+        None => true,
+    };
+    let management = match cancelled {
+        true => Err(cancelled_error(server)),
+        false => management,
+    };
+
+    match management {
+        Ok(management) => Ok(ActiveServer {
+            pid,
+            server: server.to_owned(),
+            protocol: protocol.to_owned(),
+            management: Some(management),
+            connected_at: SystemTime::now(),
+        }),
+        Err(err) => {
+            log::error!("Connecting to {} failed, stopping openvpn", server.name);
+            if let Err(err) = kill_openvpn(&pid) {
+                log::error!("Unable to stop openvpn process {pid}: {err}");
+            }
+            let _ = openvpn::delete_pidfile();
+
+            Err(err)
+        }
+    }
+}
+
+/// Records the pid of the pending connection, fails if the connection was cancelled in the meantime.
+fn set_pending_pid(state: &DaemonState, pid: &Pid) -> Result<()> {
+    let mut pending = state.pending.lock();
+
+    match *pending {
+        Some(ref mut pending) if !pending.cancelled => {
+            pending.pid = Some(pid.to_owned());
+            Ok(())
+        }
+        Some(ref pending) => Err(cancelled_error(&pending.server)),
+        None => anyhow::bail!("pending connection disappeared"),
+    }
+}
+
+fn cancelled_error(server: &LogicalServer) -> anyhow::Error {
+    DaemonError::new(
+        ErrorCode::Cancelled,
+        format!("Connection to {} was cancelled", server.name),
+    )
+    .into()
+}
+
+/// Aborts the connection that's currently being established, if any. The openvpn process is killed
+/// right away so the connecting request fails quickly and releases the connection lock.
+/// Returns the pid of the killed process.
+fn cancel_pending(state: &DaemonState) -> Option<Pid> {
+    let mut pending = state.pending.lock();
+    let pending = pending.as_mut()?;
+
+    log::info!("Cancelling pending connection to {}", pending.server.name);
+    pending.cancelled = true;
+    let pid = pending.pid.to_owned()?;
+
+    if let Err(err) = kill_openvpn(&pid) {
+        log::error!("Unable to stop pending openvpn process {pid}: {err}");
+    }
+
+    Some(pid)
+}
+
+/// Appends the session that just ended to the connection history.
+fn record_session(active: &ActiveServer, reason: &str) {
+    let status = active
+        .management
+        .as_ref()
+        .map(|management| management.status())
+        .unwrap_or_default();
+
+    let session = Session {
+        server: active.server.name.to_owned(),
+        server_id: active.server.id.to_owned(),
+        protocol: active.protocol,
+        started_at: utils::unix_timestamp(active.connected_at),
+        ended_at: utils::unix_timestamp(SystemTime::now()),
+        reason: reason.to_string(),
+        bytes_in: status.bytes_in,
+        bytes_out: status.bytes_out,
+    };
+
+    if let Err(err) = history::append_to(&history::path(), &session) {
+        log::error!("Unable to write connection history: {err}");
+    }
+}
+
+/// Takes over an openvpn process started by a previous instance of the daemon.
+fn adopt_connection(state: &DaemonState, connection: &PersistedConnection) -> Result<()> {
+    let mut sys = sysinfo::System::new();
+    let is_openvpn = match utils::get_process(&connection.pid, &mut sys) {
+        Some(process) => process.name().to_string_lossy().contains("openvpn"),
+        None => anyhow::bail!("process is no longer running"),
+    };
+    if !is_openvpn {
+        anyhow::bail!("process isn't openvpn");
+    }
+
+    let server = match state.server(&connection.server) {
+        Some(server) => server,
+        None => anyhow::bail!("no server found with id: {}", connection.server),
+    };
+
+    let management = match attach_management(state) {
+        Ok(management) => Some(management),
+        Err(err) => {
+            log::warn!("Unable to attach to openvpn management interface: {err}");
+            None
+        }
+    };
+
+    log::info!(
+        "Adopted openvpn process {} connected to {}",
+        connection.pid,
+        server.name
+    );
+    *state.active_server.write() = Some(ActiveServer {
+        pid: connection.pid.to_owned(),
+        server: server.to_owned(),
+        protocol: connection.protocol,
+        management,
+        connected_at: UNIX_EPOCH + Duration::from_secs(connection.connected_at),
+    });
+    supervisor::spawn(state, connection.pid.to_owned());
+
+    // The tunnel is already up, a failure here shouldn't make it look like an orphan
+    if *state.killswitch_enabled.read() {
+        if let Err(err) = killswitch::enable(&connection.protocol, &server.entry_ips()) {
+            log::error!("Unable to allow the adopted tunnel in the killswitch: {err}");
+        }
+    }
+    protect_ipv6();
+
+    Ok(())
+}
+
+/// Connects to openvpn's management interface and forwards state changes to subscribers.
+fn attach_management(state: &DaemonState) -> Result<Arc<Management>> {
+    let path = openvpn::management_socket_path();
+    let state = state.clone();
+
+    let on_change = move |status: &TunnelStatus| {
+        log::debug!("OpenVPN tunnel state: {}", status.state);
+        broadcast(&state, Event::Tunnel(status.state.to_owned()));
+    };
+
+    let management = Management::connect(&path, MANAGEMENT_TIMEOUT, on_change)?;
+    Ok(Arc::new(management))
+}
+
+pub fn handle_stop_request(state: &DaemonState) -> Result<()> {
+    log::info!("Stopping daemon");
+
+    let config = config::read()?;
+    let server = state.active_server.read();
+    let killswitch_enabled = state.killswitch_enabled.read();
+
+    for _ in 0..3 {
+        let result = cleanup_vpn_process(&server);
+        if result.is_ok() {
+            if *killswitch_enabled && !config.killswitch.permanent {
+                killswitch::disable()?;
+            }
+
+            break;
+        }
+    }
+
+    std::process::exit(0);
+}
+
+pub fn handle_killswitch_request(state: &DaemonState, enable: &bool) -> Result<()> {
+    log::debug!("Handling killswitch request, setting state to {enable}");
+    let config = config::read()?;
+    let _guard = state.connection_lock.lock();
+
+    if !enable && config.killswitch.permanent {
+        return Err(DaemonError::new(
+            ErrorCode::KillswitchPermanent,
+            "Can't disable the killswitch while killswitch.permanent is set",
+        )
+        .into());
+    }
+
+    let env = match (state.active_server.read().clone(), enable) {
+        (Some(server), true) => {
+            killswitch::enable(&server.protocol, &server.server.entry_ips())?;
+            HookEnv::server(&server.server, &server.protocol)
+        }
+        (Some(server), false) => {
+            killswitch::disable()?;
+            HookEnv::server(&server.server, &server.protocol)
+        }
+        (None, true) if config.killswitch.permanent => {
+            lockdown(state)?;
+            HookEnv::default()
+        }
+        // Left behind by the permanent killswitch after it was turned off
+        (None, false) => {
+            killswitch::disable()?;
+            HookEnv::default()
+        }
+        (None, true) => {
+            return Err(DaemonError::new(
+                ErrorCode::NoActiveConnection,
+                "Can't enable killswitch as there is no active vpn connection",
+            )
+            .into())
+        }
+    };
+
+    let mut enabled = state.killswitch_enabled.write();
+    *enabled = enable.to_owned();
+    drop(enabled);
+    persist::save(state);
+
+    broadcast(state, Event::Killswitch(*enable));
+
+    let env = HookEnv {
+        killswitch: Some(*enable),
+        ..env
+    };
+    hooks::run(Hook::OnKillswitchChange, &env);
+
+    log::debug!("Sucessfully set killswitch");
+
+    Ok(())
+}
+
+/// Applies the permanent killswitch while disconnected, the last used server stays reachable
+/// so it can be reconnected to.
+fn lockdown(state: &DaemonState) -> Result<()> {
+    let config = config::read()?;
+    let last_used = persist::read().and_then(|persisted| persisted.last_used);
+    let (protocol, entry_ips) = last_used
+        .and_then(|last| Some((last.protocol, state.server(&last.server)?.entry_ips())))
+        .unwrap_or((config.default_protocol, Vec::new()));
+
+    log::info!("Applying permanent killswitch");
+    killswitch::lockdown(&protocol, &entry_ips)
+}
+
+/// Opens a connection to the daemon and performs the version handshake.
+pub fn connect() -> Result<UnixStream> {
+    let socket = cache::get_path().join("socket");
+
+    let mut stream = match UnixStream::connect(&socket) {
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::PermissionDenied => {
+                let path = cache::get_path().join("socket");
+                anyhow::bail!(
+                    "Permission denied opening {}, add your user to the daemon's `socket_group` or run as root",
+                    path.to_str().unwrap()
+                )
+            }
+            _ => anyhow::bail!("unable to open socket, {err}"),
+        },
+        Ok(stream) => stream,
+    };
+
+    Request::Hello {
+        version: PROTOCOL_VERSION,
+    }
+    .write_to(&mut stream)?;
+
+    match Response::read_from(&mut stream)? {
+        Some(Response::Hello { version }) if version == PROTOCOL_VERSION => Ok(stream),
+        Some(Response::Hello { version }) => {
+            anyhow::bail!("daemon uses protocol version {version}, expected {PROTOCOL_VERSION}")
+        }
+        Some(res) => match res.into_result() {
+            Err(err) => Err(err),
+            Ok(res) => anyhow::bail!("unexpected handshake response from daemon: {res:?}"),
+        },
+        None => anyhow::bail!("daemon closed the connection during handshake"),
+    }
+}
+
+/// Sends a single request and waits for the response, `Response::Error` is returned as an `Err`.
+pub fn send_request(req: Request) -> Result<Response> {
+    let mut stream = connect()?;
+
+    if req.write_to(&mut stream).is_err() {
+        anyhow::bail!("couldn't send message")
+    }
+
+    match Response::read_from(&mut stream)? {
+        Some(res) => res.into_result(),
+        None => anyhow::bail!("daemon closed the connection without responding"),
+    }
+}
+
+/// Subscribes to the daemon's event stream, the iterator ends when the daemon hangs up.
+pub fn subscribe() -> Result<impl Iterator<Item = Result<Event>>> {
+    let mut stream = connect()?;
+    Request::Subscribe.write_to(&mut stream)?;
+
+    match Response::read_from(&mut stream)? {
+        Some(res) => res.into_result()?,
+        None => anyhow::bail!("daemon closed the connection without responding"),
+    };
+
+    Ok(std::iter::from_fn(move || {
+        match Response::read_from(&mut stream) {
+            Ok(Some(Response::Event(event))) => Some(Ok(event)),
+            Ok(Some(res)) => Some(Err(anyhow::anyhow!(
+                "unexpected response from daemon: {res:?}"
+            ))),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }))
+}
+
+fn bind_socket() -> Result<UnixListener> {
+    let socket = cache::get_path().join("socket");
+    if socket.exists() {
+        if let Err(err) = std::fs::remove_file(&socket) {
+            log::error!("Unable to delete socket file, error: {}", err);
+            return Err(err.into());
+        }
+    }
+
+    let listener = UnixListener::bind(&socket)?;
+
+    // Only root and members of `socket_group` can talk to the daemon
+    let config = config::read()?;
+    if let Some(ref group) = config.socket_group {
+        std::os::unix::fs::chown(&socket, None, Some(utils::group_id(group)?))?;
+    }
+    std::fs::set_permissions(&socket, Permissions::from_mode(0o660))?;
+
+    Ok(listener)
+}
+
+fn spawn_signal_handler(state: &DaemonState) -> Result<()> {
+    log::debug!("Spawning exit signal handler");
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let state = state.clone();
+
+    std::thread::spawn(move || {
+        log::trace!("Spawned signal handler thread");
+
+        #[allow(clippy::never_loop)]
+        for sig in signals.forever() {
+            let has_pending = state.pending.lock().is_some();
+            if has_pending && cancel_pending(&state).is_none() {
+                // openvpn might have been started without having written its pidfile yet
+                if let Ok(pid) = openvpn::read_pidfile() {
+                    let _ = kill_openvpn(&pid);
+                }
+            }
+
+            let active_server = state.active_server.read();
+
+            let config = config::read().expect("config to be initialized");
+            if config.resume == Resume::Adopt && active_server.is_some() {
+                log::info!("Received signal {sig}, leaving openvpn running so it can be adopted");
+                std::process::exit(0);
+            }
+
+            if config.killswitch.permanent {
+                log::info!("Leaving permanent killswitch in place");
+            } else if let Err(err) = killswitch::disable() {
+                log::error!("Unable to disable killswitch, error: {err}")
+            }
+
+            log::debug!("Received signal {}, cleaning up processes", sig);
+            let res = cleanup_vpn_process(&active_server);
+            restore_ipv6();
+            match res {
+                Ok(()) => {
+                    if let Some(ref active) = *active_server {
+                        record_session(active, "daemon stopped");
+                    }
+                }
+                Err(err) => log::error!("Error while cleaning up vpn process: {}", err),
+            };
+            std::process::exit(0);
+        }
+    });
+
+    Ok(())
+}
+
+/// Disables ipv6 outside the tunnel if `disable_ipv6` is set, failures are only logged
+fn protect_ipv6() {
+    let config = config::read().expect("config to be initialized");
+    if !config.disable_ipv6 {
+        return;
+    }
+
+    let Some(device) = openvpn::read_nic() else {
+        log::error!("Unable to disable ipv6, tunnel device not found");
+        return;
+    };
+    if let Err(err) = ipv6::disable(&[device.as_str()]) {
+        log::error!("Unable to disable ipv6: {err}");
+    }
+}
+
+/// Also restores after `disable_ipv6` was turned off while connected
+fn restore_ipv6() {
+    if let Err(err) = ipv6::restore() {
+        log::error!("Unable to restore ipv6 settings: {err}");
+    }
+}
+
+/// Blocking function!
+fn cleanup_vpn_process(active_server: &Option<ActiveServer>) -> Result<()> {
+    log::trace!("Attempting to cleanup openvpn process");
+
+    match active_server {
+        Some(active) => kill_openvpn(&active.pid)?,
+        None => log::debug!("No active openvpn process found, skipping cleanup"),
+    }
+
+    Ok(())
+}
+
+/// Blocking function!
+fn kill_openvpn(pid: &Pid) -> Result<()> {
+    match utils::kill_process(pid, Signal::Term) {
+        Ok(_) => {
+            log::debug!("Sent SIGTERM to child process: {}", pid);
+        }
+        Err(err) => {
+            utils::kill_process(pid, Signal::Kill)?;
+            log::error!("Unable to stop process, retrying with SIGKILL, {}", err)
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use super::DaemonState;
+use crate::{
+    cache,
+    client::{openvpn::Protocol, Pid},
+    protocol::ServerId,
+    utils,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Written whenever the connection or killswitch changes so a restarted daemon can pick up where it left off.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistedState {
+    pub connection: Option<PersistedConnection>,
+    pub killswitch: bool,
+    /// Kept after disconnecting, used by `Startup::LastUsed`
+    pub last_used: Option<LastUsed>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastUsed {
+    pub server: ServerId,
+    pub protocol: Protocol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedConnection {
+    pub server: ServerId,
+    pub protocol: Protocol,
+    pub pid: Pid,
+    /// Seconds since the unix epoch
+    pub connected_at: u64,
+}
+
+pub fn path() -> PathBuf {
+    cache::get_path().join("state.ron")
+}
+
+impl PersistedState {
+    /// Returns `None` if the file doesn't exist
+    pub fn read_from(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(ron::from_str(&content)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        utils::write_ron(path, self)
+    }
+}
+
+pub fn read() -> Option<PersistedState> {
+    match PersistedState::read_from(&path()) {
+        Ok(state) => state,
+        Err(err) => {
+            log::warn!("Ignoring unreadable state file: {err}");
+            None
+        }
+    }
+}
+
+/// Persists the current daemon state, failures are only logged as they shouldn't fail the request.
+pub fn save(state: &DaemonState) {
+    let connection = state
+        .active_server
+        .read()
+        .as_ref()
+        .map(|active| PersistedConnection {
+            server: active.server.id.to_owned(),
+            protocol: active.protocol,
+            pid: active.pid.to_owned(),
+            connected_at: utils::unix_timestamp(active.connected_at),
+        });
+    let last_used = match connection {
+        Some(ref connection) => Some(LastUsed {
+            server: connection.server.to_owned(),
+            protocol: connection.protocol,
+        }),
+        None => read().and_then(|persisted| persisted.last_used),
+    };
+
+    let persisted = PersistedState {
+        connection,
+        killswitch: *state.killswitch_enabled.read(),
+        last_used,
+    };
+
+    log::trace!(persisted:?; "Saving daemon state");
+    if let Err(err) = persisted.write_to(&path()) {
+        log::error!("Unable to save daemon state: {err}");
+    }
+}
@@ -0,0 +1,63 @@
+use super::{broadcast, DaemonState};
+use crate::{api, config, protocol::Event};
+use anyhow::Result;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the refresh thread checks whether the interval ran out, keeps up with config reloads
+const TICK: Duration = Duration::from_secs(60);
+
+/// Refreshes the server list every `server_refresh_interval` minutes
+pub fn spawn(state: &DaemonState) {
+    let state = state.clone();
+    thread::spawn(move || {
+        let mut last_refresh = Instant::now();
+
+        loop {
+            thread::sleep(TICK);
+
+            let interval = config::read()
+                .expect("config to be initialized")
+                .server_refresh_interval;
+            if interval == 0 || last_refresh.elapsed() < Duration::from_secs(interval * 60) {
+                continue;
+            }
+
+            if let Err(err) = refresh(&state) {
+                log::warn!("Unable to refresh server list: {err}");
+            }
+            // Also reset after a failure so an unreachable api isn't hammered every tick
+            last_refresh = Instant::now();
+        }
+    });
+}
+
+/// Fetches the server list and swaps it in, returns the number of servers.
+/// Only the load of the active server is updated, its entry ips have to stay the same while connected.
+pub fn refresh(state: &DaemonState) -> Result<usize> {
+    log::debug!("Refreshing server list");
+    let servers = api::fetch_logicals()?;
+    if servers.is_empty() {
+        anyhow::bail!("api returned no servers, keeping the current server list");
+    }
+
+    if let Some(ref mut active) = *state.active_server.write() {
+        match servers.iter().find(|server| server.id == active.server.id) {
+            Some(server) => {
+                active.server.load = server.load;
+                active.server.score = server.score;
+            }
+            None => log::warn!("Active server {} is no longer listed", active.server.name),
+        }
+    }
+
+    let count = servers.len();
+    *state.servers.write() = servers;
+
+    log::info!("Refreshed server list, {count} servers available");
+    broadcast(state, Event::ServersRefreshed { servers: count });
+
+    Ok(count)
+}
@@ -0,0 +1,144 @@
+use super::{broadcast, handle_killswitch_request, lockdown, DaemonState};
+use crate::{config, killswitch, protocol::Event};
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use std::{
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Editors tend to write a file in several steps, wait for them to settle before reloading
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Reloads the configuration on SIGHUP and whenever the configuration file changes.
+pub fn spawn(state: &DaemonState) -> Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+    let signal_state = state.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            log::info!("Received SIGHUP, reloading configuration");
+            reload(&signal_state);
+        }
+    });
+
+    let Some(path) = config::path() else {
+        log::debug!("No configuration file loaded, not watching for changes");
+        return Ok(());
+    };
+    // Watch the directory as editors usually replace the file instead of writing to it
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(tx, notify::Config::default())?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let state = state.clone();
+    thread::spawn(move || {
+        // Moved into the thread so it isn't dropped, which would stop the watcher
+        let _watcher = watcher;
+
+        while let Ok(event) = rx.recv() {
+            if !changes_config(&event, path) {
+                continue;
+            }
+
+            // The directory also holds the log and state files, only changes to the
+            // configuration postpone the reload or openvpn's logging could hold it off forever
+            let mut deadline = Instant::now() + DEBOUNCE;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                match rx.recv_timeout(remaining) {
+                    Ok(event) if changes_config(&event, path) => {
+                        deadline = Instant::now() + DEBOUNCE
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            log::info!("Configuration file changed, reloading");
+            reload(&state);
+        }
+    });
+
+    Ok(())
+}
+
+fn changes_config(event: &notify::Result<notify::Event>, path: &Path) -> bool {
+    match event {
+        Ok(event) => {
+            matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.iter().any(|changed| changed == path)
+        }
+        Err(err) => {
+            log::error!("Error while watching configuration file: {err}");
+            false
+        }
+    }
+}
+
+fn reload(state: &DaemonState) {
+    let previous = match config::reload() {
+        Ok(previous) => previous,
+        Err(err) => {
+            log::error!("Keeping the current configuration, new configuration is invalid: {err}");
+            broadcast(state, Event::Error(format!("invalid configuration: {err}")));
+            return;
+        }
+    };
+    let config = config::read().expect("config to be initialized");
+
+    let rules_changed = previous.killswitch.custom_rules != config.killswitch.custom_rules
+        || previous.killswitch.backend != config.killswitch.backend
+        || previous.killswitch.permanent != config.killswitch.permanent
+        || previous.killswitch.dns != config.killswitch.dns;
+    let no_longer_permanent = previous.killswitch.permanent && !config.killswitch.permanent;
+    let enabled = *state.killswitch_enabled.read();
+
+    if config.killswitch.permanent && !enabled {
+        log::info!("Killswitch is now permanent, enabling it");
+        if let Err(err) = handle_killswitch_request(state, &true) {
+            log::error!("Unable to enable permanent killswitch: {err}");
+            broadcast(
+                state,
+                Event::Error(format!("unable to enable permanent killswitch: {err}")),
+            );
+        }
+    } else if no_longer_permanent && enabled && state.active_server.read().is_none() {
+        log::info!("Killswitch is no longer permanent, disabling it while disconnected");
+        if let Err(err) = handle_killswitch_request(state, &false) {
+            log::error!("Unable to disable killswitch: {err}");
+            broadcast(
+                state,
+                Event::Error(format!("unable to disable killswitch: {err}")),
+            );
+        }
+    } else if rules_changed && enabled {
+        let _guard = state.connection_lock.lock();
+
+        log::info!("Killswitch rules changed, reapplying killswitch");
+        let result = match state.active_server.read().clone() {
+            Some(active) => killswitch::enable(&active.protocol, &active.server.entry_ips()),
+            None if config.killswitch.permanent => lockdown(state),
+            None => Ok(()),
+        };
+
+        if let Err(err) = result {
+            log::error!("Unable to reapply killswitch: {err}");
+            broadcast(
+                state,
+                Event::Error(format!("unable to reapply killswitch: {err}")),
+            );
+        }
+    }
+
+    log::info!("Reloaded configuration");
+    broadcast(state, Event::ConfigReloaded);
+}
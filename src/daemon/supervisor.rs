@@ -0,0 +1,136 @@
+use super::{
+    broadcast, handle_connect_request, persist, record_session, restore_ipv6, DaemonState,
+};
+use crate::{
+    client::{openvpn, Pid},
+    config,
+    hooks::{self, Hook, HookEnv},
+    protocol::Event,
+    utils,
+};
+use std::{thread, time::Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches an openvpn process and resets the daemon state when it exits unexpectedly.
+/// The thread stops as soon as the active server no longer belongs to `pid`.
+pub fn spawn(state: &DaemonState, pid: Pid) {
+    let state = state.clone();
+
+    thread::spawn(move || {
+        log::trace!("Supervising openvpn process {pid}");
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            if !is_active(&state, &pid) {
+                log::trace!("Process {pid} is no longer active, stopping supervisor");
+                return;
+            }
+
+            if !utils::process_exists(&pid) {
+                handle_exit(&state, pid);
+                return;
+            }
+        }
+    });
+}
+
+fn is_active(state: &DaemonState, pid: &Pid) -> bool {
+    match *state.active_server.read() {
+        Some(ref active) => &active.pid == pid,
+        None => false,
+    }
+}
+
+fn handle_exit(state: &DaemonState, pid: Pid) {
+    let guard = state.connection_lock.lock();
+
+    // A disconnect or connect request might have stopped the process while we waited for the lock
+    if !is_active(state, &pid) {
+        return;
+    }
+
+    let active = state
+        .active_server
+        .write()
+        .take()
+        .expect("an active server");
+    let auth_failed = match active.management {
+        Some(ref management) => management.status().auth_failed,
+        None => false,
+    };
+    let reason = match auth_failed {
+        true => Some("authentication failed".to_string()),
+        false => openvpn::read_exit_reason(),
+    };
+
+    log::error!(
+        "OpenVPN process {pid} for {} exited unexpectedly, reason: {}",
+        active.server.name,
+        reason.as_deref().unwrap_or("unknown")
+    );
+
+    *state.last_exit.write() = Some(reason.clone().unwrap_or_else(|| "openvpn exited".into()));
+    let _ = openvpn::delete_pidfile();
+    restore_ipv6();
+    persist::save(state);
+    record_session(&active, reason.as_deref().unwrap_or("openvpn exited"));
+    drop(guard);
+
+    if auth_failed {
+        let server = active.server.name.to_owned();
+        broadcast(state, Event::AuthFailed { server });
+    }
+    let env = HookEnv {
+        reason: reason.clone(),
+        ..HookEnv::server(&active.server, &active.protocol)
+    };
+    broadcast(
+        state,
+        Event::OpenvpnExited {
+            pid,
+            reason,
+            unexpected: true,
+        },
+    );
+    broadcast(state, Event::Disconnected);
+    hooks::run(Hook::OnFailure, &env);
+
+    reconnect(state, &active.server.id, &active.protocol);
+}
+
+fn reconnect(state: &DaemonState, server_id: &str, protocol: &openvpn::Protocol) {
+    let config = config::read().expect("config to be initialized");
+    let policy = &config.reconnect;
+
+    if !policy.enable {
+        return;
+    }
+
+    for attempt in 0..policy.max_retries {
+        let backoff = Duration::from_secs(policy.backoff.saturating_mul(1 << attempt.min(16)));
+        log::info!(
+            "Reconnecting in {}s, attempt {}/{}",
+            backoff.as_secs(),
+            attempt + 1,
+            policy.max_retries
+        );
+        thread::sleep(backoff);
+
+        let pending = state.pending.lock().is_some();
+        if pending || state.active_server.read().is_some() {
+            log::debug!("Connection was re-established by a request, cancelling reconnect");
+            return;
+        }
+
+        match handle_connect_request(&[server_id.to_owned()], protocol, state) {
+            Ok(_) => return,
+            Err(err) => log::error!("Reconnect attempt {} failed: {err}", attempt + 1),
+        }
+    }
+
+    let message = format!("Unable to reconnect after {} attempts", policy.max_retries);
+    log::error!("{message}");
+    broadcast(state, Event::Error(message));
+}
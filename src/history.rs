@@ -0,0 +1,117 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{cache, client::openvpn::Protocol, protocol::ServerId};
+
+/// A finished vpn session, the history file contains one per line as json.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub server: String,
+    pub server_id: ServerId,
+    pub protocol: Protocol,
+    /// Seconds since the unix epoch
+    pub started_at: u64,
+    /// Seconds since the unix epoch
+    pub ended_at: u64,
+    /// Why the session ended, e.g. `disconnected` or the reason openvpn exited with
+    pub reason: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filter {
+    /// Matches the server name or id, case insensitive
+    pub server: Option<String>,
+    pub protocol: Option<Protocol>,
+    /// Only sessions that ended after this, in seconds since the unix epoch
+    pub since: Option<u64>,
+    /// Only keep the most recent sessions
+    pub limit: Option<usize>,
+}
+
+pub fn path() -> PathBuf {
+    cache::get_path().join("history.jsonl")
+}
+
+impl Session {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.ended_at.saturating_sub(self.started_at))
+    }
+
+    pub fn matches(&self, filter: &Filter) -> bool {
+        let server = match filter.server {
+            Some(ref query) => {
+                self.server.to_lowercase().contains(&query.to_lowercase())
+                    || self.server_id.eq_ignore_ascii_case(query)
+            }
+            None => true,
+        };
+        let protocol = match filter.protocol {
+            Some(protocol) => protocol == self.protocol,
+            None => true,
+        };
+        let since = match filter.since {
+            Some(since) => self.ended_at >= since,
+            None => true,
+        };
+
+        server && protocol && since
+    }
+}
+
+pub fn append_to(path: &Path, session: &Session) -> Result<()> {
+    // Only the daemon reads it, clients go through `Request::History`
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(session)?)?;
+
+    Ok(())
+}
+
+/// Returns the sessions oldest first, lines that can't be parsed are skipped.
+pub fn read_from(path: &Path) -> Result<Vec<Session>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let sessions = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(session) => Some(session),
+            Err(err) => {
+                log::warn!("Skipping malformed history entry: {err}");
+                None
+            }
+        })
+        .collect();
+
+    Ok(sessions)
+}
+
+pub fn filter(sessions: Vec<Session>, filter: &Filter) -> Vec<Session> {
+    let mut sessions = sessions
+        .into_iter()
+        .filter(|session| session.matches(filter))
+        .collect::<Vec<_>>();
+
+    if let Some(limit) = filter.limit {
+        let skip = sessions.len().saturating_sub(limit);
+        sessions.drain(..skip);
+    }
+
+    sessions
+}
@@ -0,0 +1,225 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    net::Ipv4Addr,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Sender},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api::LogicalServer,
+    client::openvpn::Protocol,
+    config::{self, Hooks},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreConnect,
+    PostConnect,
+    PreDisconnect,
+    PostDisconnect,
+    /// Connecting failed or openvpn exited unexpectedly
+    OnFailure,
+    OnKillswitchChange,
+}
+
+/// Describes the connection to hook commands, every field that's set is passed as a `PVPN_*` variable.
+#[derive(Debug, Clone, Default)]
+pub struct HookEnv {
+    pub server: Option<String>,
+    pub server_id: Option<String>,
+    pub protocol: Option<Protocol>,
+    pub interface: Option<String>,
+    pub tunnel_ip: Option<Ipv4Addr>,
+    pub reason: Option<String>,
+    pub killswitch: Option<bool>,
+}
+
+impl Hook {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PreConnect => "pre_connect",
+            Self::PostConnect => "post_connect",
+            Self::PreDisconnect => "pre_disconnect",
+            Self::PostDisconnect => "post_disconnect",
+            Self::OnFailure => "on_failure",
+            Self::OnKillswitchChange => "on_killswitch_change",
+        }
+    }
+
+    fn commands<'a>(&self, hooks: &'a Hooks) -> &'a [String] {
+        match self {
+            Self::PreConnect => &hooks.pre_connect,
+            Self::PostConnect => &hooks.post_connect,
+            Self::PreDisconnect => &hooks.pre_disconnect,
+            Self::PostDisconnect => &hooks.post_disconnect,
+            Self::OnFailure => &hooks.on_failure,
+            Self::OnKillswitchChange => &hooks.on_killswitch_change,
+        }
+    }
+}
+
+impl HookEnv {
+    pub fn server(server: &LogicalServer, protocol: &Protocol) -> Self {
+        Self {
+            server: Some(server.name.to_owned()),
+            server_id: Some(server.id.to_owned()),
+            protocol: Some(*protocol),
+            ..Default::default()
+        }
+    }
+
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    pub fn vars(&self, hook: Hook) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("PVPN_HOOK", hook.name().to_string())];
+        let mut push = |key, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((key, value));
+            }
+        };
+
+        push("PVPN_SERVER", self.server.to_owned());
+        push("PVPN_SERVER_ID", self.server_id.to_owned());
+        push("PVPN_PROTOCOL", self.protocol.map(|val| val.to_string()));
+        push("PVPN_INTERFACE", self.interface.to_owned());
+        push("PVPN_TUNNEL_IP", self.tunnel_ip.map(|val| val.to_string()));
+        push("PVPN_REASON", self.reason.to_owned());
+        push(
+            "PVPN_KILLSWITCH",
+            self.killswitch.map(|val| match val {
+                true => "1".to_string(),
+                false => "0".to_string(),
+            }),
+        );
+
+        vars
+    }
+}
+
+struct Job {
+    hook: Hook,
+    env: HookEnv,
+    /// Notified once the commands finished
+    done: Option<Sender<()>>,
+}
+
+/// Queues the commands configured for `hook`, they run on a separate thread so slow hooks don't
+/// hold up requests. Hooks still run one after another in the order they were queued.
+pub fn run(hook: Hook, env: &HookEnv) {
+    let job = Job {
+        hook,
+        env: env.to_owned(),
+        done: None,
+    };
+    if queue().send(job).is_err() {
+        log::error!("Hook runner stopped, not running {} hook", hook.name());
+    }
+}
+
+/// Like `run` but waits until the commands finished, for hooks that have to run before something happens
+pub fn run_blocking(hook: Hook, env: &HookEnv) {
+    let (tx, rx) = mpsc::channel();
+    let job = Job {
+        hook,
+        env: env.to_owned(),
+        done: Some(tx),
+    };
+    if queue().send(job).is_err() {
+        log::error!("Hook runner stopped, not running {} hook", hook.name());
+        return;
+    }
+    let _ = rx.recv();
+}
+
+fn queue() -> &'static Sender<Job> {
+    static QUEUE: OnceLock<Sender<Job>> = OnceLock::new();
+
+    QUEUE.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in rx {
+                run_commands(job.hook, &job.env);
+                if let Some(done) = job.done {
+                    let _ = done.send(());
+                }
+            }
+        });
+        tx
+    })
+}
+
+/// Runs the commands configured for `hook` one after another, blocking until they finish.
+/// Failures are only logged, a broken hook shouldn't keep the vpn from connecting.
+fn run_commands(hook: Hook, env: &HookEnv) {
+    let config = config::read().expect("config to be initialized");
+    let timeout = Duration::from_secs(config.hooks.timeout);
+
+    for command in hook.commands(&config.hooks) {
+        if let Err(err) = run_command(hook, command, env, timeout) {
+            log::error!("{} hook `{command}` failed: {err}", hook.name());
+        }
+    }
+}
+
+/// Runs a single command with `sh -c`, it's killed once `timeout` runs out.
+pub fn run_command(
+    hook: Hook,
+    command: &str,
+    env: &HookEnv,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    log::info!("Running {} hook `{command}`", hook.name());
+
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .envs(env.vars(hook))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Not joined, a command that leaves a background process behind would keep the pipes open
+    log_output(hook, child.stdout.take());
+    log_output(hook, child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("timed out after {}s", timeout.as_secs());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    if !status.success() {
+        anyhow::bail!("exited with {status}");
+    }
+
+    Ok(())
+}
+
+fn log_output(hook: Hook, output: Option<impl Read + Send + 'static>) {
+    thread::spawn(move || {
+        let Some(output) = output else {
+            return;
+        };
+
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            log::info!("{} hook: {line}", hook.name());
+        }
+    });
+}
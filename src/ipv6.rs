@@ -0,0 +1,92 @@
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{cache, utils};
+
+const CONF_DIR: &str = "/proc/sys/net/ipv6/conf";
+
+/// `disable_ipv6` values from before ipv6 was disabled, keyed by interface
+type Saved = BTreeMap<String, String>;
+
+pub fn saved_path() -> PathBuf {
+    cache::get_path().join("ipv6.ron")
+}
+
+/// Sets `disable_ipv6` on every interface except loopback and `except`, the previous values are saved
+/// so `restore` can put them back even after the daemon restarted.
+pub fn disable(except: &[&str]) -> Result<()> {
+    disable_in(Path::new(CONF_DIR), &saved_path(), except)
+}
+
+/// Restores the values saved by `disable`, does nothing if ipv6 wasn't disabled
+pub fn restore() -> Result<()> {
+    restore_in(Path::new(CONF_DIR), &saved_path())
+}
+
+pub fn disable_in(conf_dir: &Path, saved_path: &Path, except: &[&str]) -> Result<()> {
+    let entries = match std::fs::read_dir(conf_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            log::debug!("{conf_dir:?} doesn't exist, ipv6 isn't available");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Values that are already saved are kept, they're the ones from before the first disable
+    let mut saved = read_saved(saved_path)?.unwrap_or_default();
+
+    for entry in entries {
+        let interface = entry?.file_name().to_string_lossy().to_string();
+        // `all` and `default` would also apply to the tunnel
+        if ["all", "default", "lo"].contains(&interface.as_str())
+            || except.contains(&interface.as_str())
+        {
+            continue;
+        }
+
+        let path = conf_dir.join(&interface).join("disable_ipv6");
+        let value = std::fs::read_to_string(&path)?.trim().to_string();
+        saved.entry(interface.to_owned()).or_insert(value);
+        // Written before changing anything so a crash can't lose the original values
+        utils::write_ron(saved_path, &saved)?;
+
+        std::fs::write(&path, "1")?;
+        log::debug!("Disabled ipv6 on {interface}");
+    }
+
+    Ok(())
+}
+
+pub fn restore_in(conf_dir: &Path, saved_path: &Path) -> Result<()> {
+    let Some(saved) = read_saved(saved_path)? else {
+        return Ok(());
+    };
+
+    for (interface, value) in saved {
+        let path = conf_dir.join(&interface).join("disable_ipv6");
+        // The interface might have disappeared in the meantime
+        if !path.exists() {
+            continue;
+        }
+
+        std::fs::write(&path, &value)?;
+        log::debug!("Restored disable_ipv6 = {value} on {interface}");
+    }
+
+    std::fs::remove_file(saved_path)?;
+    log::info!("Restored ipv6 settings");
+
+    Ok(())
+}
+
+fn read_saved(path: &Path) -> Result<Option<Saved>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(ron::from_str(&content)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
@@ -0,0 +1,219 @@
+use super::{Firewall, Policy};
+use crate::{cache, cmd, utils::Cmd};
+use anyhow::Result;
+use std::path::Path;
+
+/// The killswitch lives in its own chains which the builtin chains jump to first.
+/// Allowed traffic returns to the builtin chain so the host's own rules still apply to it,
+/// everything else is dropped. The same chains are added to ip6tables so ipv6 can't bypass the tunnel.
+pub struct Iptables;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+/// Builtin chain and the chain of the killswitch it jumps to
+pub const CHAINS: [(&str, &str); 3] = [
+    ("INPUT", "PROTONVPN_RS_INPUT"),
+    ("OUTPUT", "PROTONVPN_RS_OUTPUT"),
+    ("FORWARD", "PROTONVPN_RS_FORWARD"),
+];
+
+impl Firewall for Iptables {
+    fn enable(&self, policy: &Policy) -> Result<()> {
+        for family in Family::available() {
+            log::trace!(
+                "Applying {} killswitch rules, protocol: {}",
+                family.program(),
+                policy.protocol
+            );
+
+            let missing_jumps = Self::missing_jumps(family);
+            let ruleset = Self::ruleset(family, policy, &missing_jumps);
+            Cmd::new(family.restore_program())
+                .args(&["--noflush"])
+                .input(&ruleset)?;
+        }
+        log::trace!("Successfully applied iptables killswitch rules");
+
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<()> {
+        log::trace!("Removing iptables killswitch chains");
+
+        for family in Family::available() {
+            let program = family.program();
+            for (builtin, chain) in CHAINS {
+                // Removes duplicate jumps as well, e.g. after only some of them were found while enabling
+                while cmd!(program, "-D", builtin, "-j", chain).exec().is_ok() {}
+
+                if cmd!(program, "-S", chain).exec().is_ok() {
+                    cmd!(program, "-F", chain).exec()?;
+                    cmd!(program, "-X", chain).exec()?;
+                }
+            }
+        }
+
+        let legacy_backup = cache::get_path().join("iptables.backup");
+        if legacy_backup.exists() {
+            log::warn!(
+                "Older versions replaced all iptables rules, restore {legacy_backup:?} with iptables-restore if they're still active"
+            );
+        }
+
+        log::info!("Removed iptables killswitch chains");
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        let [_, (_, output), _] = CHAINS;
+        cmd!(Family::V4.program(), "-S", output).exec().is_ok()
+    }
+}
+
+impl Iptables {
+    /// Input for `iptables-restore --noflush`, declaring the chains flushes them so the killswitch
+    /// is replaced in one go. Jumps are only added from the builtin chains in `missing_jumps`.
+    /// The exceptions and custom rules are ipv4 only, with ipv6 nothing but the tunnel and loopback is allowed.
+    pub fn ruleset(family: Family, policy: &Policy, missing_jumps: &[&str]) -> String {
+        let [(_, input), (_, output), (_, forward)] = CHAINS;
+        let mut rules = vec!["*filter".to_string()];
+
+        for (_, chain) in CHAINS {
+            rules.push(format!(":{chain} - [0:0]"));
+        }
+
+        rules.extend([
+            format!("-A {input} -i lo -j RETURN"),
+            format!("-A {output} -o lo -j RETURN"),
+        ]);
+
+        if let Some(ref device) = policy.device {
+            rules.extend([
+                format!("-A {input} -i {device} -j RETURN"),
+                format!("-A {output} -o {device} -j RETURN"),
+            ]);
+        }
+
+        if policy.dhcp {
+            rules.extend(match family {
+                Family::V4 => vec![
+                    format!("-A {output} -p udp -m udp --sport 68 --dport 67 -j RETURN"),
+                    format!("-A {input} -p udp -m udp --sport 67 --dport 68 -j RETURN"),
+                ],
+                // Router and neighbor solicitations and advertisements, then dhcpv6
+                Family::V6 => vec![
+                    format!("-A {output} -p ipv6-icmp -m icmp6 --icmpv6-type 133 -j RETURN"),
+                    format!("-A {input} -p ipv6-icmp -m icmp6 --icmpv6-type 134 -j RETURN"),
+                    format!("-A {output} -p ipv6-icmp -m icmp6 --icmpv6-type 135 -j RETURN"),
+                    format!("-A {input} -p ipv6-icmp -m icmp6 --icmpv6-type 135 -j RETURN"),
+                    format!("-A {output} -p ipv6-icmp -m icmp6 --icmpv6-type 136 -j RETURN"),
+                    format!("-A {input} -p ipv6-icmp -m icmp6 --icmpv6-type 136 -j RETURN"),
+                    format!("-A {output} -p udp -m udp --sport 546 --dport 547 -j RETURN"),
+                    format!("-A {input} -p udp -m udp --sport 547 --dport 546 -j RETURN"),
+                ],
+            });
+        }
+
+        if family == Family::V4 {
+            for (ips, protocols, ports) in policy.exceptions() {
+                for ip in ips {
+                    for proto in &protocols {
+                        for port in ports.iter() {
+                            rules.extend([
+                                format!("-A {output} -d {ip} -p {proto} -m {proto} --dport {port} -j RETURN"),
+                                format!("-A {input} -s {ip} -p {proto} -m {proto} --sport {port} -j RETURN"),
+                            ]);
+                        }
+                    }
+                }
+            }
+
+            rules.extend(
+                policy
+                    .custom_rules
+                    .iter()
+                    .map(|rule| Self::custom_rule(rule)),
+            );
+        }
+
+        rules.extend([
+            format!("-A {input} -j DROP"),
+            format!("-A {output} -j DROP"),
+            format!("-A {forward} -j DROP"),
+        ]);
+
+        for (builtin, chain) in CHAINS {
+            if missing_jumps.contains(&builtin) {
+                rules.push(format!("-I {builtin} 1 -j {chain}"));
+            }
+        }
+
+        rules.push("COMMIT".into());
+
+        let mut ruleset = rules.join("\n");
+        ruleset.push('\n');
+        ruleset
+    }
+
+    /// Custom rules are written against the builtin chains, they're moved into the killswitch chains
+    /// and `ACCEPT` becomes `RETURN` so they're evaluated before everything else is dropped.
+    pub fn custom_rule(rule: &str) -> String {
+        let mut previous = "";
+        rule.split_whitespace()
+            .map(|arg| {
+                let arg = match (previous, arg) {
+                    ("-A" | "-I", chain) => CHAINS
+                        .iter()
+                        .find(|(builtin, _)| *builtin == chain)
+                        .map_or(chain, |(_, chain)| chain),
+                    ("-j", "ACCEPT") => "RETURN",
+                    (_, arg) => arg,
+                };
+                previous = arg;
+                arg
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Builtin chains that don't jump to the killswitch, e.g. after firewalld flushed only some of them
+    fn missing_jumps(family: Family) -> Vec<&'static str> {
+        CHAINS
+            .into_iter()
+            .filter(|(builtin, chain)| {
+                cmd!(family.program(), "-C", builtin, "-j", chain)
+                    .exec()
+                    .is_err()
+            })
+            .map(|(builtin, _)| builtin)
+            .collect()
+    }
+}
+
+impl Family {
+    /// ip6tables is skipped when the kernel doesn't support ipv6
+    pub fn available() -> Vec<Family> {
+        match Path::new("/proc/net/if_inet6").exists() {
+            true => vec![Family::V4, Family::V6],
+            false => vec![Family::V4],
+        }
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            Self::V4 => "iptables",
+            Self::V6 => "ip6tables",
+        }
+    }
+
+    fn restore_program(&self) -> &'static str {
+        match self {
+            Self::V4 => "iptables-restore",
+            Self::V6 => "ip6tables-restore",
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use super::{iptables::Iptables, nftables::Nftables};
+use crate::{
+    api, cache,
+    client::openvpn::{self, Protocol},
+    cmd,
+    config::{self, Configuration, FirewallBackend},
+    utils::{self, Cmd},
+};
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, Ipv4Addr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+/// The backend the killswitch was enabled with, so it's removed from the right firewall
+/// after `killswitch.backend` changed.
+static ACTIVE: Mutex<Option<FirewallBackend>> = Mutex::new(None);
+
+/// A firewall the killswitch can be implemented with
+pub trait Firewall {
+    /// Blocks all traffic that isn't allowed by `policy`
+    fn enable(&self, policy: &Policy) -> Result<()>;
+    /// Removes the killswitch and restores the previous rules
+    fn disable(&self) -> Result<()>;
+    /// Whether the killswitch is in place, also when it was applied by another process, e.g. at boot
+    fn is_enabled(&self) -> bool;
+}
+
+/// What stays reachable while the killswitch is active
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// The tunnel, not set while disconnected
+    pub device: Option<String>,
+    pub protocol: Protocol,
+    pub entry_ips: Vec<Ipv4Addr>,
+    /// Resolvers reachable on port 53, only used by the permanent killswitch
+    pub dns: Vec<Ipv4Addr>,
+    /// Addresses of the api reachable on port 443, only used by the permanent killswitch
+    pub api: Vec<Ipv4Addr>,
+    /// Allows dhcp and ipv6 neighbor discovery so the host still gets an address while the
+    /// permanent killswitch blocks everything else, e.g. when it's applied at boot
+    pub dhcp: bool,
+    pub custom_rules: Vec<String>,
+}
+
+/// Saved whenever the permanent killswitch is applied, so it can be applied again
+/// at boot before the network or the daemon are up.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lockdown {
+    pub protocol: Protocol,
+    pub entry_ips: Vec<Ipv4Addr>,
+    pub api: Vec<Ipv4Addr>,
+}
+
+impl Policy {
+    /// Addresses allowed besides the tunnel, together with the protocols and ports they're allowed on
+    pub fn exceptions(&self) -> Vec<(&[Ipv4Addr], Vec<Protocol>, Vec<u32>)> {
+        vec![
+            (
+                &self.entry_ips,
+                vec![self.protocol],
+                self.protocol.default_ports().to_vec(),
+            ),
+            (&self.dns, vec![Protocol::Udp, Protocol::Tcp], vec![53]),
+            (&self.api, vec![Protocol::Tcp], vec![443]),
+        ]
+    }
+}
+
+/// Blocks everything except the tunnel and the server's entry ips, the tunnel has to be up
+pub fn enable(proto: &Protocol, entry_ips: &[Ipv4Addr]) -> Result<()> {
+    apply_policy(openvpn::read_nic(), proto, entry_ips, true)
+}
+
+/// Allows the entry ips of the server that's about to be connected to, the tunnel isn't up yet.
+/// Before the first connection the tunnel device isn't known and only the entry ips are allowed.
+pub fn allow_server(proto: &Protocol, entry_ips: &[Ipv4Addr]) -> Result<()> {
+    apply_policy(openvpn::read_nic(), proto, entry_ips, false)
+}
+
+/// The permanent killswitch while there's no tunnel, only the entry ips of the server
+/// that's reconnected to, the configured resolvers and the api stay reachable.
+pub fn lockdown(proto: &Protocol, entry_ips: &[Ipv4Addr]) -> Result<()> {
+    apply_policy(None, proto, entry_ips, false)
+}
+
+/// Applies the permanent killswitch saved by the daemon without resolving anything,
+/// used at boot before the network is up.
+pub fn lockdown_from_saved() -> Result<()> {
+    let config = config::read()?;
+    if !config.killswitch.permanent {
+        log::warn!("killswitch.permanent isn't set, not applying the killswitch");
+        return Ok(());
+    }
+
+    let saved = read_lockdown().unwrap_or_else(|err| {
+        log::warn!("Unable to read saved killswitch, only dns is allowed: {err}");
+        Lockdown::default()
+    });
+
+    apply(&Policy {
+        device: None,
+        protocol: saved.protocol,
+        entry_ips: saved.entry_ips,
+        dns: config.killswitch.dns.clone(),
+        api: saved.api,
+        dhcp: true,
+        custom_rules: config.killswitch.custom_rules.clone().unwrap_or_default(),
+    })
+}
+
+/// Applies the killswitch and saves the permanent one for the next boot
+fn apply_policy(
+    device: Option<String>,
+    proto: &Protocol,
+    entry_ips: &[Ipv4Addr],
+    tunnel_up: bool,
+) -> Result<()> {
+    let config = config::read()?;
+    let policy = policy(&config, device, proto, entry_ips, tunnel_up);
+    apply(&policy)?;
+
+    if config.killswitch.permanent {
+        let lockdown = Lockdown {
+            protocol: policy.protocol,
+            entry_ips: policy.entry_ips,
+            api: policy.api,
+        };
+        if let Err(err) = utils::write_ron(&lockdown_path(), &lockdown) {
+            log::error!("Unable to save killswitch for the next boot: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+fn policy(
+    config: &Configuration,
+    device: Option<String>,
+    proto: &Protocol,
+    entry_ips: &[Ipv4Addr],
+    tunnel_up: bool,
+) -> Policy {
+    let mut policy = Policy {
+        device,
+        protocol: *proto,
+        entry_ips: entry_ips.to_vec(),
+        custom_rules: config.killswitch.custom_rules.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+
+    if config.killswitch.permanent {
+        policy.dns = config.killswitch.dns.clone();
+        policy.dhcp = true;
+        // Without the tunnel an applied killswitch blocks the system resolver,
+        // trying anyway would stall until the lookup times out
+        policy.api = match tunnel_up || !is_applied(config) {
+            true => resolve_api(),
+            false => saved_api(),
+        };
+    }
+
+    policy
+}
+
+/// Checks the firewall instead of `ACTIVE`, the killswitch may have been applied
+/// at boot or before the daemon restarted
+fn is_applied(config: &Configuration) -> bool {
+    let active = *ACTIVE.lock();
+    let backend = active.unwrap_or_else(|| resolve_backend(&config.killswitch.backend));
+
+    firewall(&backend).is_enabled()
+}
+
+fn apply(policy: &Policy) -> Result<()> {
+    let config = config::read()?;
+    let backend = resolve_backend(&config.killswitch.backend);
+
+    let mut active = ACTIVE.lock();
+    if let Some(previous) = active.filter(|previous| *previous != backend) {
+        log::info!("Killswitch backend changed, removing {previous:?} killswitch");
+        firewall(&previous).disable()?;
+        *active = None;
+    }
+
+    log::debug!("Using {backend:?} killswitch backend");
+    firewall(&backend).enable(policy)?;
+    *active = Some(backend);
+
+    Ok(())
+}
+
+pub fn disable() -> Result<()> {
+    let mut active = ACTIVE.lock();
+    let backend = match *active {
+        Some(backend) => backend,
+        None => resolve_backend(&config::read()?.killswitch.backend),
+    };
+
+    firewall(&backend).disable()?;
+    *active = None;
+
+    Ok(())
+}
+
+/// Addresses of the api, falls back to the ones that were saved last time when resolving fails
+fn resolve_api() -> Vec<Ipv4Addr> {
+    let resolved = (api::DOMAIN, 443).to_socket_addrs().map(|addrs| {
+        addrs
+            .filter_map(|addr| match addr.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                IpAddr::V6(_) => None,
+            })
+            .collect::<Vec<_>>()
+    });
+
+    match resolved {
+        Ok(ips) if !ips.is_empty() => ips,
+        resolved => {
+            if let Err(err) = resolved {
+                log::warn!("Unable to resolve {}: {err}", api::DOMAIN);
+            }
+            saved_api()
+        }
+    }
+}
+
+fn saved_api() -> Vec<Ipv4Addr> {
+    read_lockdown().map(|saved| saved.api).unwrap_or_default()
+}
+
+pub fn lockdown_path() -> PathBuf {
+    cache::get_path().join("lockdown.ron")
+}
+
+fn read_lockdown() -> Result<Lockdown> {
+    let content = std::fs::read_to_string(lockdown_path())?;
+    Ok(ron::from_str(&content)?)
+}
+
+fn firewall(backend: &FirewallBackend) -> Box<dyn Firewall> {
+    match backend {
+        FirewallBackend::Nftables => Box::new(Nftables),
+        _ => Box::new(Iptables),
+    }
+}
+
+fn resolve_backend(backend: &FirewallBackend) -> FirewallBackend {
+    match backend {
+        FirewallBackend::Auto => detect_backend(),
+        backend => backend.to_owned(),
+    }
+}
+
+/// Prefers nftables when `nft` is installed and `iptables` is missing or only a wrapper around nftables
+fn detect_backend() -> FirewallBackend {
+    if cmd!("nft", "--version").output().is_err() {
+        return FirewallBackend::Iptables;
+    }
+
+    match cmd!("iptables", "--version").output() {
+        Ok(version) if !version.contains("nf_tables") => FirewallBackend::Iptables,
+        _ => FirewallBackend::Nftables,
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(target_os = "linux")]
+pub use iptables::{Family, Iptables};
+#[cfg(target_os = "linux")]
+pub use linux::*;
+#[cfg(target_os = "linux")]
+pub use nftables::Nftables;
+
+#[cfg(target_os = "linux")]
+mod iptables;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+mod nftables;
+
+#[cfg(target_os = "macos")]
+pub use macos::*;
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use crate::{
+        cache,
+        client::openvpn::{self, Protocol},
+        cmd, config, rules,
+        utils::Cmd,
+    };
+    use anyhow::Result;
+    use std::{fs::File, net::Ipv4Addr, path::PathBuf};
+
+    pub struct Pf;
+    type Rule = String;
+
+    pub fn enable(protocol: &Protocol, entry_ips: &[Ipv4Addr]) -> Result<()> {
+        let logfile = File::open(cache::get_path().join("ovpn.log"))?;
+        let config = config::read()?;
+
+        let device = openvpn::parse_nic(logfile).expect("device name");
+        let mut rules = rules![
+            "block drop all",   // block all traffic by default
+            "pass on lo0",      // allow traffic on loopback interface
+            "pass on {device}"  // allow traffic over vpn tunnel
+        ];
+
+        for port in protocol.default_ports() {
+            for ip in entry_ips {
+                rules.push(format!(
+                    "pass out proto {protocol} from any to {ip} port {port}"
+                ))
+            }
+        }
+
+        if let Some(custom_rules) = config.killswitch.custom_rules.clone() {
+            rules.extend_from_slice(custom_rules.as_slice());
+        }
+
+        Pf::apply_rules(rules)
+    }
+
+    pub fn disable() -> Result<()> {
+        Pf::restore()
+    }
+
+    pub fn allow_server(protocol: &Protocol, entry_ips: &[Ipv4Addr]) -> Result<()> {
+        enable(protocol, entry_ips)
+    }
+
+    pub fn lockdown(_protocol: &Protocol, _entry_ips: &[Ipv4Addr]) -> Result<()> {
+        anyhow::bail!("The permanent killswitch is only supported on linux")
+    }
+
+    pub fn lockdown_from_saved() -> Result<()> {
+        anyhow::bail!("The permanent killswitch is only supported on linux")
+    }
+
+    impl Pf {
+        fn restore() -> Result<()> {
+            log::trace!("flushing pf rules");
+            cmd!("pfctl", "-F", "all").output()?;
+
+            Ok(())
+        }
+
+        fn apply_rules(contents: Vec<Rule>) -> Result<()> {
+            let mut contents = contents.join("\n");
+            // if we don't terminate the last line pfctl can't parse the config
+            contents.extend(["\n"]);
+
+            let config_path = Self::config_path();
+            std::fs::write(&config_path, contents)?;
+
+            cmd!("pfctl", "-f", config_path.to_str().unwrap()).exec()?;
+            cmd!("pfctl", "-E").exec()?;
+
+            log::info!("Successfully applied pf rules");
+            Ok(())
+        }
+
+        fn config_path() -> PathBuf {
+            cache::get_path().join("pf.conf")
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! rules {
+    [$($rule:expr),*] => {{
+        let mut rules = Vec::new();
+        $(
+            rules.push(format!($rule));
+        )*
+        rules
+    }};
+}
+
+#[macro_export]
+macro_rules! cmd {
+    ($program:expr $(,$arg:expr)*) => {
+       Cmd::new($program).args(&[$($arg),*])
+    };
+}
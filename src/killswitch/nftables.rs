@@ -0,0 +1,117 @@
+use super::{Firewall, Policy};
+use crate::{cmd, utils::Cmd};
+use anyhow::Result;
+
+/// The killswitch lives in its own table so it can be replaced or dropped in a single transaction
+/// without touching the rest of the host's ruleset. Being an `inet` table it covers ipv4 and ipv6.
+pub struct Nftables;
+
+pub const TABLE: &str = "protonvpn_rs";
+
+impl Firewall for Nftables {
+    fn enable(&self, policy: &Policy) -> Result<()> {
+        log::trace!(
+            "Applying nftables killswitch table, protocol: {}",
+            policy.protocol
+        );
+        cmd!("nft", "-f", "-").input(&Self::ruleset(policy))?;
+        log::trace!("Successfully applied nftables killswitch table");
+
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<()> {
+        log::trace!("Deleting nftables killswitch table");
+        // Declaring the table first makes the delete succeed when it doesn't exist
+        cmd!("nft", "-f", "-")
+            .input(&format!("table inet {TABLE}\ndelete table inet {TABLE}\n"))?;
+
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        cmd!("nft", "list", "table", "inet", TABLE).exec().is_ok()
+    }
+}
+
+impl Nftables {
+    /// Script for `nft -f`, replaces the killswitch table atomically.
+    /// Custom rules are appended as separate commands, e.g. `add rule inet protonvpn_rs output ip daddr 192.168.0.0/24 accept`
+    pub fn ruleset(policy: &Policy) -> String {
+        let mut input = vec![r#"iif "lo" accept"#.to_string()];
+        let mut output = vec![r#"oif "lo" accept"#.to_string()];
+
+        if let Some(ref device) = policy.device {
+            input.push(format!(r#"iifname "{device}" accept"#));
+            output.push(format!(r#"oifname "{device}" accept"#));
+        }
+
+        if policy.dhcp {
+            input.extend([
+                "udp sport 67 udp dport 68 accept".to_string(),
+                "udp sport 547 udp dport 546 accept".to_string(),
+                "icmpv6 type { nd-router-advert, nd-neighbor-solicit, nd-neighbor-advert } accept"
+                    .to_string(),
+            ]);
+            output.extend([
+                "udp sport 68 udp dport 67 accept".to_string(),
+                "udp sport 546 udp dport 547 accept".to_string(),
+                "icmpv6 type { nd-router-solicit, nd-neighbor-solicit, nd-neighbor-advert } accept"
+                    .to_string(),
+            ]);
+        }
+
+        for (ips, protocols, ports) in policy.exceptions() {
+            // An empty set isn't valid, without ips there's nothing to allow
+            if ips.is_empty() {
+                continue;
+            }
+
+            let (ips, ports) = (join(ips), join(&ports));
+            for proto in protocols {
+                input.push(format!(
+                    "ip saddr {{ {ips} }} {proto} sport {{ {ports} }} accept"
+                ));
+                output.push(format!(
+                    "ip daddr {{ {ips} }} {proto} dport {{ {ports} }} accept"
+                ));
+            }
+        }
+
+        let indent = "\n        ";
+        let (input, output) = (input.join(indent), output.join(indent));
+        let mut ruleset = format!(
+            r#"table inet {TABLE}
+delete table inet {TABLE}
+table inet {TABLE} {{
+    chain input {{
+        type filter hook input priority filter; policy drop;
+        {input}
+    }}
+    chain output {{
+        type filter hook output priority filter; policy drop;
+        {output}
+    }}
+    chain forward {{
+        type filter hook forward priority filter; policy drop;
+    }}
+}}
+"#
+        );
+
+        for rule in policy.custom_rules.iter() {
+            ruleset.push_str(rule);
+            ruleset.push('\n');
+        }
+
+        ruleset
+    }
+}
+
+fn join(values: &[impl ToString]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
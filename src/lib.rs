@@ -4,7 +4,11 @@ pub mod cli;
 pub mod client;
 pub mod config;
 pub mod daemon;
+pub mod history;
+pub mod hooks;
+pub mod ipv6;
 pub mod killswitch;
+pub mod notify;
 pub mod protocol;
 pub mod service;
 pub mod utils;
@@ -0,0 +1,148 @@
+use anyhow::Result;
+use std::process::Command;
+
+use crate::{config::Notifications, protocol::Event};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+}
+
+/// Turns daemon events into notifications, keeps track of what happened before
+/// so a dropped connection isn't also reported as a regular disconnect.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    exited_unexpectedly: bool,
+    auth_failed: bool,
+}
+
+impl Notification {
+    fn new(summary: impl Into<String>, body: impl Into<String>, urgency: Urgency) -> Self {
+        Self {
+            summary: summary.into(),
+            body: body.into(),
+            urgency,
+        }
+    }
+
+    /// Arguments for `gdbus call`, matching `org.freedesktop.Notifications.Notify`
+    pub fn gdbus_args(&self) -> Vec<String> {
+        let urgency = match self.urgency {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        };
+
+        [
+            "--session",
+            "--dest",
+            "org.freedesktop.Notifications",
+            "--object-path",
+            "/org/freedesktop/Notifications",
+            "--method",
+            "org.freedesktop.Notifications.Notify",
+        ]
+        .into_iter()
+        .map(String::from)
+        .chain([
+            gvariant_string("protonvpn-rs"),
+            "uint32 0".into(),
+            gvariant_string("network-vpn"),
+            gvariant_string(&self.summary),
+            gvariant_string(&self.body),
+            "@as []".into(),
+            format!("{{'urgency': <byte {urgency}>}}"),
+            "int32 -1".into(),
+        ])
+        .collect()
+    }
+
+    /// Sends the notification over the session bus
+    pub fn send(&self) -> Result<()> {
+        let output = Command::new("gdbus")
+            .arg("call")
+            .args(self.gdbus_args())
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("unable to send notification: {}", stderr.trim())
+        }
+
+        Ok(())
+    }
+}
+
+impl Notifier {
+    pub fn handle(&mut self, event: &Event, config: &Notifications) -> Option<Notification> {
+        match event {
+            Event::Connecting { .. } => {
+                *self = Self::default();
+                None
+            }
+            Event::Connected { server, protocol } => {
+                *self = Self::default();
+                config.connected.then(|| {
+                    Notification::new(
+                        "VPN connected",
+                        format!("Connected to {server} ({protocol})"),
+                        Urgency::Low,
+                    )
+                })
+            }
+            Event::OpenvpnExited {
+                reason,
+                unexpected: true,
+                ..
+            } => {
+                self.exited_unexpectedly = true;
+                // Already reported as an authentication failure
+                if std::mem::take(&mut self.auth_failed) {
+                    return None;
+                }
+                let body = reason.as_deref().unwrap_or("openvpn exited unexpectedly");
+                config
+                    .dropped
+                    .then(|| Notification::new("VPN connection dropped", body, Urgency::Critical))
+            }
+            Event::Disconnected => {
+                let dropped = std::mem::take(&mut self.exited_unexpectedly);
+                (config.disconnected && !dropped)
+                    .then(|| Notification::new("VPN disconnected", "", Urgency::Normal))
+            }
+            Event::Killswitch(enabled) => config.killswitch.then(|| {
+                let summary = match enabled {
+                    true => "Killswitch enabled",
+                    false => "Killswitch disabled",
+                };
+                Notification::new(summary, "", Urgency::Normal)
+            }),
+            Event::AuthFailed { server } => {
+                self.auth_failed = true;
+                config.auth_failed.then(|| {
+                    Notification::new(
+                        "VPN authentication failed",
+                        format!("{server} rejected the openvpn credentials"),
+                        Urgency::Critical,
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Quotes a string as a GVariant text literal so gdbus doesn't try to parse its contents
+fn gvariant_string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('\'', "\\'");
+    format!("'{escaped}'")
+}
@@ -1,123 +1,344 @@
 use anyhow::Result;
-use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddrV4},
+};
 
-use crate::client::{openvpn::Protocol, Pid};
+use crate::{
+    api::{Country, Features, LogicalServer, Ordering},
+    client::{
+        management::TunnelState,
+        openvpn::{ConnectError, Protocol},
+        Pid,
+    },
+    config::{Filters, Select},
+    history::{Filter, Session},
+};
+
+/// Bump this whenever `Request` or `Response` change in a way older peers can't decode.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Upper bound for a single frame, prevents allocating garbage lengths from a misbehaving peer.
+pub const MAX_FRAME_LEN: u32 = 1024 * 1024;
 
+/// Messages are sent as frames: a big endian u32 length prefix followed by a json payload.
 pub trait SocketProtocol {
-    fn deserialize(data: &str) -> Result<Self>
+    fn deserialize(data: &[u8]) -> Result<Self>
     where
         Self: Sized;
     fn serialize(&self) -> Vec<u8>;
+
+    /// Reads a single frame, returns `None` if the peer closed the connection between frames.
+    fn read_from(reader: &mut impl Read) -> Result<Option<Self>>
+    where
+        Self: Sized,
+    {
+        match read_frame(reader)? {
+            Some(frame) => Ok(Some(Self::deserialize(&frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        write_frame(writer, &self.serialize())
+    }
 }
 
-type ServerId = String;
+pub type ServerId = String;
 type Enable = bool;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Request {
+    /// Has to be the first message on every connection
+    Hello {
+        version: u32,
+    },
     Status,
     Disconnect,
-    Connect(ServerId, Protocol),
+    /// Candidates are tried in order until one connects, see `Configuration.failover`
+    Connect(Vec<ServerId>, Protocol),
+    /// Lets the daemon pick the servers matching the criteria, ranked by `Select`
+    ConnectMatching(Filters, Select, Protocol),
+    /// Servers matching the criteria from the daemon's server list, optionally sorted and limited
+    Query(Filters, Option<Ordering>, Option<usize>),
     Killswitch(Enable),
+    /// Fetches the server list from the api instead of waiting for the next scheduled refresh
+    RefreshServers,
+    /// Finished sessions matching the filter, the history file is only readable by the daemon
+    History(Filter),
+    /// Keeps the connection open, the daemon answers with `Response::Ok` and then
+    /// sends a `Response::Event` for every state change until the client hangs up.
+    Subscribe,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Response {
+    Hello {
+        version: u32,
+    },
+    Ok,
     Status(ServerStatus),
+    Connected {
+        server: String,
+        protocol: Protocol,
+        /// Attempts that failed before the connection succeeded
+        failed: Vec<FailedAttempt>,
+    },
+    Servers(Vec<LogicalServer>),
+    /// Number of servers in the new server list
+    Refreshed {
+        servers: usize,
+    },
+    /// Oldest first
+    History(Vec<Session>),
+    Event(Event),
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
 }
 
-#[derive(Debug)]
-pub enum ServerStatus {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    Connecting {
+        server: String,
+        protocol: Protocol,
+    },
     Connected {
-        name: String,
-        pid: Pid,
+        server: String,
         protocol: Protocol,
     },
     Disconnected,
+    Killswitch(Enable),
+    /// The tunnel state reported by openvpn changed
+    Tunnel(TunnelState),
+    /// The server rejected the credentials, sent before the connect request fails or openvpn exits
+    AuthFailed {
+        server: String,
+    },
+    /// `unexpected` is set when the process wasn't stopped by the daemon, e.g. it crashed or was killed.
+    /// `reason` is `None` when openvpn didn't log why it exited
+    OpenvpnExited {
+        pid: Pid,
+        reason: Option<String>,
+        unexpected: bool,
+    },
+    /// The daemon picked up a changed configuration file
+    ConfigReloaded,
+    ServersRefreshed {
+        servers: usize,
+    },
+    Error(String),
 }
 
-fn split_message(msg: &str) -> (&str, Vec<&str>) {
-    let parts = msg.split(':').collect::<Vec<_>>();
-    let (command, args) = parts.split_at(1);
-    let command = *command.first().expect("an instruction command");
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedAttempt {
+    pub server: String,
+    pub protocol: Protocol,
+    pub error: String,
+}
 
-    (command, args.to_vec())
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerStatus {
+    Connected(ConnectionInfo),
+    Connecting {
+        name: String,
+        protocol: Protocol,
+    },
+    /// `last_exit` is why openvpn exited on its own, cleared by the next connect
+    Disconnected {
+        last_exit: Option<String>,
+    },
 }
 
-impl SocketProtocol for Request {
-    fn deserialize(data: &str) -> Result<Self> {
-        let (command, args) = split_message(data);
-
-        match command {
-            "status" => Ok(Self::Status),
-            "disconnect" => Ok(Self::Disconnect),
-            "connect" => match args.as_slice() {
-                [server_id, protocol] => Ok(Self::Connect(
-                    server_id.to_string(),
-                    Protocol::from_str(protocol, true).expect("valid protocol"),
-                )),
-                _ => anyhow::bail!("incorrect arguments"),
-            },
-            "killswitch" => match args.as_slice() {
-                ["true"] => Ok(Self::Killswitch(true)),
-                ["false"] => Ok(Self::Killswitch(false)),
-                _ => anyhow::bail!("incorrect arguments"),
-            },
-            _ => anyhow::bail!("no command matched"),
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub name: String,
+    pub pid: Pid,
+    pub protocol: Protocol,
+    /// `None` if the daemon couldn't attach to openvpn's management interface
+    pub state: Option<TunnelState>,
+    /// Seconds since the unix epoch
+    pub connected_since: u64,
+    /// The tun device, `None` if it couldn't be found
+    pub interface: Option<String>,
+    pub tunnel_ip: Option<Ipv4Addr>,
+    /// Entry ip and port of the server
+    pub remote: Option<SocketAddrV4>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub killswitch: bool,
+    pub country: Country,
+    pub load: u8,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// The client speaks a different protocol version than the daemon
+    VersionMismatch,
+    /// A request was sent before the `Hello` handshake completed
+    HandshakeRequired,
+    /// The frame couldn't be decoded into a `Request`
+    MalformedRequest,
+    ServerNotFound,
+    NoActiveConnection,
+    /// The `openvpn` binary couldn't be found in the daemon's PATH
+    OpenvpnMissing,
+    AuthFailed,
+    /// The tunnel didn't come up within `connect_timeout`
+    ConnectTimeout,
+    /// OpenVPN exited before the tunnel came up
+    ConnectFailed,
+    /// The connection was aborted by a disconnect or another connect request
+    Cancelled,
+    /// The killswitch can't be disabled while `killswitch.permanent` is set
+    KillswitchPermanent,
+    /// The request was valid but handling it failed
+    Internal,
+}
+
+/// Error returned by the daemon, use `anyhow::Error::downcast_ref` to inspect the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl DaemonError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
         }
     }
+}
 
-    fn serialize(&self) -> Vec<u8> {
-        match self {
-            Self::Status => "status".into(),
-            Self::Connect(id, protocol) => format!("connect:{id}:{protocol}"),
-            Self::Disconnect => "disconnect".into(),
-            Self::Killswitch(enable) => format!("killswitch:{enable}"),
+impl std::fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for DaemonError {}
+
+impl From<ConnectError> for DaemonError {
+    fn from(err: ConnectError) -> Self {
+        let code = match err {
+            ConnectError::BinaryMissing => ErrorCode::OpenvpnMissing,
+            ConnectError::AuthFailed => ErrorCode::AuthFailed,
+            ConnectError::Timeout(..) => ErrorCode::ConnectTimeout,
+            ConnectError::Exited(_) => ErrorCode::ConnectFailed,
+        };
+
+        Self::new(code, err.to_string())
+    }
+}
+
+impl From<DaemonError> for Response {
+    fn from(err: DaemonError) -> Self {
+        Response::Error {
+            code: err.code,
+            message: err.message,
         }
-        .as_bytes()
-        .to_vec()
     }
 }
 
-impl SocketProtocol for Response {
-    fn deserialize(data: &str) -> Result<Self> {
-        let (command, args) = split_message(data);
-
-        match command {
-            "status" => {
-                let status = match args.as_slice() {
-                    ["disconnected"] => ServerStatus::Disconnected,
-                    ["connected", pid, name, protocol] => {
-                        let pid = Pid::try_from(pid.to_string())?;
-                        ServerStatus::Connected {
-                            name: name.to_string(),
-                            pid,
-                            protocol: Protocol::from_str(protocol, true).expect("valid protocol"),
-                        }
-                    }
-                    _ => anyhow::bail!("no such status or invalid arguments"),
-                };
-
-                Ok(Response::Status(status))
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting { server, protocol } => {
+                write!(f, "connecting to {server} ({protocol})")
             }
-            _ => anyhow::bail!("unknown command"),
+            Self::Connected { server, protocol } => write!(f, "connected to {server} ({protocol})"),
+            Self::Disconnected => write!(f, "disconnected"),
+            Self::Killswitch(true) => write!(f, "killswitch enabled"),
+            Self::Killswitch(false) => write!(f, "killswitch disabled"),
+            Self::Tunnel(state) => write!(f, "tunnel state changed to {state}"),
+            Self::AuthFailed { server } => write!(f, "authentication failed for {server}"),
+            Self::OpenvpnExited {
+                pid,
+                reason,
+                unexpected,
+            } => {
+                write!(f, "openvpn process {pid} exited")?;
+                if *unexpected {
+                    write!(f, " unexpectedly")?;
+                }
+                match reason {
+                    Some(reason) => write!(f, ": {reason}"),
+                    None => Ok(()),
+                }
+            }
+            Self::ConfigReloaded => write!(f, "configuration reloaded"),
+            Self::ServersRefreshed { servers } => {
+                write!(f, "refreshed server list ({servers} servers)")
+            }
+            Self::Error(message) => write!(f, "error: {message}"),
         }
     }
+}
 
-    fn serialize(&self) -> Vec<u8> {
+impl Response {
+    /// Turns a `Response::Error` into an `Err` so callers can use `?`
+    pub fn into_result(self) -> Result<Self> {
         match self {
-            Self::Status(status) => match status {
-                ServerStatus::Connected {
-                    pid,
-                    name,
-                    protocol,
-                } => {
-                    format!("status:connected:{}:{}:{}", pid, name, protocol)
-                }
-                ServerStatus::Disconnected => "status:disconnected".to_string(),
-            },
+            Response::Error { code, message } => Err(DaemonError { code, message }.into()),
+            res => Ok(res),
         }
-        .as_bytes()
-        .to_vec()
+    }
+}
+
+pub fn read_frame(reader: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    if let Err(err) = reader.read_exact(&mut len) {
+        return match err.kind() {
+            ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(err.into()),
+        };
+    }
+
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        anyhow::bail!("frame of {len} bytes exceeds maximum of {MAX_FRAME_LEN} bytes")
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+
+    Ok(Some(buf))
+}
+
+pub fn write_frame(writer: &mut impl Write, data: &[u8]) -> Result<()> {
+    let len = match u32::try_from(data.len()) {
+        Ok(len) if len <= MAX_FRAME_LEN => len,
+        _ => anyhow::bail!("frame of {} bytes exceeds maximum size", data.len()),
+    };
+
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+impl SocketProtocol for Request {
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("request to be serializable")
+    }
+}
+
+impl SocketProtocol for Response {
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("response to be serializable")
     }
 }
@@ -9,8 +9,10 @@ pub use platform::*;
 #[cfg(target_os = "linux")]
 mod platform {
     use super::*;
+    use crate::config::{self, Resume};
 
     static LABEL: &str = "protonvpn-rs.service";
+    static LOCKDOWN_LABEL: &str = "protonvpn-rs-lockdown.service";
 
     #[derive(Template)]
     #[template(path = "systemd-service")]
@@ -18,6 +20,15 @@ mod platform {
         user: String,
         group: String,
         bin: String,
+        /// Only `Resume::Adopt` leaves openvpn running when the daemon stops,
+        /// otherwise systemd cleans up whatever is left in the cgroup
+        adopt: bool,
+    }
+
+    #[derive(Template)]
+    #[template(path = "systemd-lockdown")]
+    struct SystemdLockdown {
+        bin: String,
     }
 
     pub fn generate_config() -> Result<String> {
@@ -25,14 +36,32 @@ mod platform {
             user: "root".into(),
             group: "root".into(),
             bin: utils::absolute_binary_path()?.to_str().unwrap().to_string(),
+            adopt: config::read()?.resume == Resume::Adopt,
+        };
+
+        Ok(service.render()?)
+    }
+
+    /// Oneshot unit applying the permanent killswitch before the network comes up
+    pub fn generate_lockdown_config() -> Result<String> {
+        let service = SystemdLockdown {
+            bin: utils::absolute_binary_path()?.to_str().unwrap().to_string(),
         };
 
         Ok(service.render()?)
     }
 
     pub fn install(config: &str, path: Option<&PathBuf>) -> Result<()> {
+        write_unit(config, path, LABEL)
+    }
+
+    pub fn install_lockdown(config: &str, path: Option<&PathBuf>) -> Result<()> {
+        write_unit(config, path, LOCKDOWN_LABEL)
+    }
+
+    fn write_unit(config: &str, path: Option<&PathBuf>, label: &str) -> Result<()> {
         use std::str::FromStr;
-        let default_path = format!("/etc/systemd/system/{}", LABEL);
+        let default_path = format!("/etc/systemd/system/{}", label);
         let path = match path {
             Some(path) => path,
             None => &PathBuf::from_str(&default_path).expect("valid path"),
@@ -92,6 +121,14 @@ mod platform {
         Ok(launchagent.render()?)
     }
 
+    pub fn generate_lockdown_config() -> Result<String> {
+        anyhow::bail!("The permanent killswitch is only supported on linux")
+    }
+
+    pub fn install_lockdown(_config: &str, _path: Option<&PathBuf>) -> Result<()> {
+        anyhow::bail!("The permanent killswitch is only supported on linux")
+    }
+
     fn plist_path() -> PathBuf {
         let path = format!("~/Library/LaunchAgents/{}.plist", LABEL);
         utils::home_dir().join(path.strip_prefix("~/").expect("a path"))
@@ -8,11 +8,12 @@ use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
 use std::sync::mpsc::channel;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{net::IpAddr, path::PathBuf, str::FromStr};
 use sysinfo::{Process, Signal, System};
 
-pub fn wait_for_file_and_read(path: &str) -> Result<String> {
+/// Blocks until `path` exists and returns its contents, gives up after `timeout`.
+pub fn wait_for_file_and_read(path: &str, timeout: Duration) -> Result<String> {
     let (tx, rx) = channel();
     let mut watcher: RecommendedWatcher = Watcher::new(tx, notify::Config::default())?;
 
@@ -23,29 +24,39 @@ pub fn wait_for_file_and_read(path: &str) -> Result<String> {
     ))?;
 
     watcher.watch(parent_dir, RecursiveMode::NonRecursive)?;
+    let deadline = Instant::now() + timeout;
 
     loop {
-        match rx.recv_timeout(Duration::from_secs(5)) {
-            Ok(event) => {
-                if let EventKind::Create(_) | EventKind::Modify(_) = event?.kind {
-                    if file_path.exists() {
-                        drop(watcher);
-                        let content = fs::read_to_string(file_path)?;
-                        return Ok(content);
-                    }
-                }
-            }
-            Err(_) => {
-                if file_path.exists() {
-                    drop(watcher);
-                    let content = fs::read_to_string(file_path)?;
-                    return Ok(content);
-                }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // Also when the file exists but stays empty, there'd be nothing left to wait for
+        if remaining.is_zero() {
+            anyhow::bail!("timed out after {}s waiting for {path}", timeout.as_secs());
+        }
+
+        let created = match rx.recv_timeout(remaining.min(Duration::from_secs(1))) {
+            Ok(event) => matches!(event?.kind, EventKind::Create(_) | EventKind::Modify(_)),
+            Err(_) => true,
+        };
+
+        if created && file_path.exists() {
+            let content = fs::read_to_string(file_path)?;
+            // The file might have been created but not yet written to
+            if !content.trim().is_empty() {
+                return Ok(content);
             }
         }
     }
 }
 
+/// Writes `value` as ron to a temporary file first, so a crash can't leave a truncated file behind
+pub fn write_ron(path: &Path, value: &impl Serialize) -> Result<()> {
+    let tmp = path.with_extension("ron.tmp");
+    fs::write(&tmp, ron::to_string(value)?)?;
+    fs::rename(tmp, path)?;
+
+    Ok(())
+}
+
 pub fn home_dir() -> PathBuf {
     #[allow(deprecated)] // deprecated because of windows support.
     match std::env::home_dir() {
@@ -69,6 +80,11 @@ pub fn get_process<'a>(pid: &Pid, sys: &'a mut System) -> Option<&'a Process> {
     sys.process(pid)
 }
 
+pub fn process_exists(pid: &Pid) -> bool {
+    let mut sys = sysinfo::System::new();
+    get_process(pid, &mut sys).is_some()
+}
+
 /// Blocking function!
 pub fn kill_process(pid: &Pid, signal: Signal) -> Result<()> {
     let mut sys = sysinfo::System::new();
@@ -105,6 +121,81 @@ pub fn find_nic(interface_name: &str) -> Option<NetworkInterface> {
         .find(|e| e.is_up() && !e.is_loopback() && !e.ips.is_empty() && e.name == interface_name)
 }
 
+/// Resolves a group name, or a numeric id, to its gid using the contents of `/etc/group`
+pub fn parse_group_id(etc_group: &str, group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+
+    etc_group.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        match (fields.next(), fields.nth(1)) {
+            (Some(name), Some(gid)) if name == group => gid.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+pub fn group_id(group: &str) -> Result<u32> {
+    let etc_group = fs::read_to_string("/etc/group")?;
+    match parse_group_id(&etc_group, group) {
+        Some(gid) => Ok(gid),
+        None => anyhow::bail!("group {group} doesn't exist"),
+    }
+}
+
+pub fn unix_timestamp(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+/// Formats seconds since the unix epoch as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hours, minutes) = ((secs % 86400) / 3600, (secs % 3600) / 60);
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}")
+}
+
+/// Formats a duration as e.g. `2h 05m` or `42s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, (secs % 3600) / 60, secs % 60) {
+        (0, 0, secs) => format!("{secs}s"),
+        (0, minutes, secs) => format!("{minutes}m {secs:02}s"),
+        (hours, minutes, _) => format!("{hours}h {minutes:02}m"),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
+
 pub struct Cmd<'a> {
     program: &'a str,
     args: Option<&'a [&'a str]>,
@@ -140,7 +231,7 @@ impl<'a> Cmd<'a> {
             .args(self.args.unwrap_or_default())
             .stdin(Stdio::piped())
             .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stderr(Stdio::piped())
             .spawn()?;
 
         if let Some(mut stdin) = child.stdin.take() {
@@ -151,7 +242,13 @@ impl<'a> Cmd<'a> {
         match child.wait_with_output() {
             Ok(output) => {
                 if !output.status.success() {
-                    anyhow::bail!("something went wrong, idk");
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    anyhow::bail!(
+                        "{} exited with {}: {}",
+                        self.program,
+                        output.status,
+                        stderr.trim()
+                    );
                 }
 
                 Ok(())
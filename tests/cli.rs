@@ -0,0 +1,28 @@
+use protonvpn_rs::cli;
+
+fn requires_root(args: &[&str]) -> bool {
+    let matches = cli::init()
+        .try_get_matches_from(std::iter::once("protonvpn-rs").chain(args.iter().copied()))
+        .expect("valid arguments");
+    cli::requires_root(&matches)
+}
+
+#[test]
+fn test_requires_root() {
+    assert!(!requires_root(&["status"]));
+    assert!(!requires_root(&["query", "--limit", "5"]));
+    assert!(!requires_root(&["connect", "--fastest"]));
+    assert!(!requires_root(&["service", "install"]));
+    assert!(!requires_root(&["killswitch", "disable"]));
+    assert!(!requires_root(&[
+        "config",
+        "writedefault",
+        "--path",
+        "config.ron"
+    ]));
+
+    assert!(requires_root(&["service", "start", "--daemon"]));
+    assert!(requires_root(&["service", "install", "--write"]));
+    assert!(requires_root(&["config", "writedefault"]));
+    assert!(requires_root(&["killswitch", "lockdown"]));
+}
@@ -0,0 +1,53 @@
+use protonvpn_rs::config::{Configuration, Profile, Startup};
+use std::net::Ipv4Addr;
+
+#[test]
+fn test_startup_policy() {
+    let mut config = Configuration::default();
+    assert_eq!(config.startup(), Startup::None);
+
+    config.autostart_default = true;
+    assert_eq!(config.startup(), Startup::DefaultCriteria);
+
+    config.startup = Some(Startup::LastUsed);
+    assert_eq!(config.startup(), Startup::LastUsed);
+
+    let config = ron::from_str::<Configuration>(r#"(startup: Some(Profile("work")))"#)
+        .expect("a valid config");
+    assert_eq!(config.startup(), Startup::Profile("work".into()));
+}
+
+#[test]
+fn test_validate() {
+    assert!(Configuration::default().validate().is_ok());
+
+    let config = ron::from_str::<Configuration>("(connect_timeout: 0)").expect("a valid config");
+    assert!(config.validate().is_err());
+
+    let config = ron::from_str::<Configuration>(r#"(startup: Some(Profile("missing")))"#)
+        .expect("a valid config");
+    assert!(config.validate().is_err());
+
+    let mut config = Configuration {
+        startup: Some(Startup::Profile("work".into())),
+        ..Default::default()
+    };
+    config.profiles.insert(
+        "work".into(),
+        Profile {
+            criteria: config.default_criteria.clone(),
+            select: None,
+            protocol: None,
+        },
+    );
+    assert!(config.validate().is_ok());
+
+    config.default_criteria.max_load = 101;
+    assert!(config.validate().is_err());
+
+    let mut config = Configuration::default();
+    config.killswitch.permanent = true;
+    assert!(config.validate().is_err());
+    config.killswitch.dns = vec![Ipv4Addr::new(9, 9, 9, 9)];
+    assert_eq!(config.validate().is_ok(), cfg!(target_os = "linux"));
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use protonvpn_rs::{
+    client::openvpn::Protocol,
+    history::{self, Filter, Session},
+};
+use std::time::Duration;
+
+fn session(server: &str, protocol: Protocol, started_at: u64) -> Session {
+    Session {
+        server: server.into(),
+        server_id: format!("{server}-id"),
+        protocol,
+        started_at,
+        ended_at: started_at + 3725,
+        reason: "disconnected".into(),
+        bytes_in: 4096,
+        bytes_out: 1024,
+    }
+}
+
+#[test]
+fn test_history_roundtrip() -> Result<()> {
+    let path =
+        std::env::temp_dir().join(format!("protonvpn-rs-{}-history.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    assert!(history::read_from(&path)?.is_empty());
+
+    let sessions = [
+        session("NL#1", Protocol::Udp, 1722500000),
+        session("CH#4", Protocol::Tcp, 1722600000),
+    ];
+    for session in sessions.iter() {
+        history::append_to(&path, session)?;
+    }
+    std::fs::write(&path, std::fs::read_to_string(&path)? + "{\"truncated\n")?;
+
+    assert_eq!(history::read_from(&path)?, sessions);
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_history_filter() {
+    let mut sessions = vec![
+        session("NL#1", Protocol::Udp, 1722500000),
+        session("CH#4", Protocol::Tcp, 1722600000),
+        session("NL#7", Protocol::Udp, 1722700000),
+    ];
+
+    let filter = Filter {
+        server: Some("nl".into()),
+        ..Default::default()
+    };
+    let names = |sessions: Vec<Session>| {
+        sessions
+            .into_iter()
+            .map(|session| session.server)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(history::filter(sessions.clone(), &filter)),
+        ["NL#1", "NL#7"]
+    );
+
+    let filter = Filter {
+        protocol: Some(Protocol::Tcp),
+        ..Default::default()
+    };
+    assert_eq!(names(history::filter(sessions.clone(), &filter)), ["CH#4"]);
+
+    let filter = Filter {
+        since: Some(1722600000),
+        limit: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(names(history::filter(sessions.clone(), &filter)), ["NL#7"]);
+
+    // Proton server ids are mixed case base64
+    sessions[1].server_id = "zA9x-Kq2_Lm0bVc==".into();
+    let filter = Filter {
+        server: Some("zA9x-Kq2_Lm0bVc==".into()),
+        ..Default::default()
+    };
+    assert_eq!(names(history::filter(sessions.clone(), &filter)), ["CH#4"]);
+
+    assert_eq!(sessions[0].duration(), Duration::from_secs(3725));
+}
@@ -0,0 +1,45 @@
+use protonvpn_rs::hooks::{run_command, Hook, HookEnv};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_hook_env() {
+    let env = HookEnv {
+        server: Some("NL#1".into()),
+        interface: Some("tun0".into()),
+        tunnel_ip: Some("10.96.0.2".parse().unwrap()),
+        killswitch: Some(true),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        env.vars(Hook::PostConnect),
+        [
+            ("PVPN_HOOK", "post_connect".to_string()),
+            ("PVPN_SERVER", "NL#1".to_string()),
+            ("PVPN_INTERFACE", "tun0".to_string()),
+            ("PVPN_TUNNEL_IP", "10.96.0.2".to_string()),
+            ("PVPN_KILLSWITCH", "1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_hook_command() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("protonvpn-rs-{}-hook", std::process::id()));
+    let env = HookEnv::default().reason("tls-error");
+    let timeout = Duration::from_secs(5);
+
+    let command = format!("echo \"$PVPN_HOOK $PVPN_REASON\" > {}", path.display());
+    run_command(Hook::OnFailure, &command, &env, timeout)?;
+    assert_eq!(std::fs::read_to_string(&path)?, "on_failure tls-error\n");
+    std::fs::remove_file(path)?;
+
+    assert!(run_command(Hook::OnFailure, "exit 3", &env, timeout).is_err());
+
+    let started = Instant::now();
+    let timeout = Duration::from_millis(200);
+    assert!(run_command(Hook::OnFailure, "sleep 10", &env, timeout).is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use protonvpn_rs::ipv6::{disable_in, restore_in};
+use std::{fs, path::Path};
+
+fn read(conf_dir: &Path, interface: &str) -> String {
+    fs::read_to_string(conf_dir.join(interface).join("disable_ipv6")).expect("a value")
+}
+
+#[test]
+fn test_disable_and_restore() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("protonvpn-rs-{}-ipv6", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let conf_dir = dir.join("conf");
+    let saved = dir.join("ipv6.ron");
+
+    for (interface, value) in [
+        ("all", "0"),
+        ("lo", "0"),
+        ("eth0", "0"),
+        ("wlan0", "1"),
+        ("tun0", "0"),
+    ] {
+        fs::create_dir_all(conf_dir.join(interface))?;
+        fs::write(conf_dir.join(interface).join("disable_ipv6"), value)?;
+    }
+
+    disable_in(&conf_dir, &saved, &["tun0"])?;
+    assert_eq!(read(&conf_dir, "eth0"), "1");
+    assert_eq!(read(&conf_dir, "wlan0"), "1");
+    assert_eq!(read(&conf_dir, "tun0"), "0");
+    assert_eq!(read(&conf_dir, "lo"), "0");
+    assert_eq!(read(&conf_dir, "all"), "0");
+
+    // Disabling again, e.g. after switching servers, keeps the original values
+    disable_in(&conf_dir, &saved, &["tun0"])?;
+
+    restore_in(&conf_dir, &saved)?;
+    assert_eq!(read(&conf_dir, "eth0"), "0");
+    assert_eq!(read(&conf_dir, "wlan0"), "1");
+    assert!(!saved.exists());
+
+    // Nothing to restore
+    restore_in(&conf_dir, &saved)?;
+
+    // Without ipv6 support there's nothing to do
+    disable_in(&dir.join("missing"), &saved, &[])?;
+    assert!(!saved.exists());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
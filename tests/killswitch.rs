@@ -0,0 +1,173 @@
+#![cfg(target_os = "linux")]
+
+use protonvpn_rs::{
+    client::openvpn::Protocol,
+    killswitch::{Family, Iptables, Nftables, Policy},
+};
+use std::net::Ipv4Addr;
+
+const ALL_JUMPS: [&str; 3] = ["INPUT", "OUTPUT", "FORWARD"];
+const ENTRY_IPS: [Ipv4Addr; 2] = [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)];
+
+fn policy(protocol: Protocol, custom_rules: &[String]) -> Policy {
+    Policy {
+        device: Some("tun0".into()),
+        protocol,
+        entry_ips: ENTRY_IPS.to_vec(),
+        custom_rules: custom_rules.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// The permanent killswitch while disconnected
+fn lockdown() -> Policy {
+    Policy {
+        device: None,
+        dns: vec![Ipv4Addr::new(9, 9, 9, 9)],
+        api: vec![Ipv4Addr::new(185, 159, 159, 148)],
+        dhcp: true,
+        ..policy(Protocol::Udp, &[])
+    }
+}
+
+#[test]
+fn test_iptables_ruleset() {
+    let custom = vec!["-A OUTPUT -d 192.168.0.0/24 -j ACCEPT".to_string()];
+    let ruleset = Iptables::ruleset(Family::V4, &policy(Protocol::Udp, &custom), &ALL_JUMPS);
+    let lines = ruleset.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines.first(), Some(&"*filter"));
+    assert_eq!(lines.last(), Some(&"COMMIT"));
+    // Never touches the host's rules or policies
+    assert!(!lines
+        .iter()
+        .any(|line| line.starts_with("-F") || line.starts_with("-P")));
+    assert!(lines.contains(&":PROTONVPN_RS_OUTPUT - [0:0]"));
+    assert!(lines.contains(&"-A PROTONVPN_RS_OUTPUT -o tun0 -j RETURN"));
+    assert!(
+        lines.contains(&"-A PROTONVPN_RS_OUTPUT -d 10.0.0.2 -p udp -m udp --dport 1194 -j RETURN")
+    );
+    assert!(
+        lines.contains(&"-A PROTONVPN_RS_INPUT -s 10.0.0.1 -p udp -m udp --sport 1194 -j RETURN")
+    );
+    // Nothing but the entry ips is reachable on the vpn ports
+    assert!(!lines
+        .iter()
+        .any(|line| line.contains("--dport") && !line.contains("-d 10.0.0.")));
+    assert!(lines.contains(&"-I INPUT 1 -j PROTONVPN_RS_INPUT"));
+
+    // Custom rules come before everything else is dropped
+    let custom = lines
+        .iter()
+        .position(|line| *line == "-A PROTONVPN_RS_OUTPUT -d 192.168.0.0/24 -j RETURN");
+    let drop = lines
+        .iter()
+        .position(|line| *line == "-A PROTONVPN_RS_OUTPUT -j DROP");
+    assert!(custom.is_some() && custom < drop);
+
+    let ruleset = Iptables::ruleset(Family::V4, &policy(Protocol::Udp, &[]), &[]);
+    assert!(!ruleset.contains("-I INPUT"));
+
+    // Only the jumps that went missing are inserted again
+    let ruleset = Iptables::ruleset(Family::V4, &policy(Protocol::Udp, &[]), &["OUTPUT"]);
+    assert!(ruleset.contains("-I OUTPUT 1 -j PROTONVPN_RS_OUTPUT"));
+    assert!(!ruleset.contains("-I INPUT"));
+    assert!(!ruleset.contains("-I FORWARD"));
+}
+
+#[test]
+fn test_ip6tables_ruleset() {
+    let custom = vec!["-A OUTPUT -d 192.168.0.0/24 -j ACCEPT".to_string()];
+    let ruleset = Iptables::ruleset(Family::V6, &policy(Protocol::Udp, &custom), &ALL_JUMPS);
+
+    assert!(ruleset.contains("-A PROTONVPN_RS_OUTPUT -o tun0 -j RETURN"));
+    assert!(ruleset.contains("-A PROTONVPN_RS_OUTPUT -j DROP"));
+    assert!(ruleset.contains("-I OUTPUT 1 -j PROTONVPN_RS_OUTPUT"));
+    // Entry ips and custom rules are ipv4 only
+    assert!(!ruleset.contains("10.0.0.1"));
+    assert!(!ruleset.contains("192.168.0.0/24"));
+}
+
+#[test]
+fn test_iptables_custom_rule() {
+    assert_eq!(
+        Iptables::custom_rule("-A INPUT -p tcp -m tcp --dport 22 -j ACCEPT"),
+        "-A PROTONVPN_RS_INPUT -p tcp -m tcp --dport 22 -j RETURN"
+    );
+    assert_eq!(
+        Iptables::custom_rule("-A OUTPUT -d 10.0.0.1 -j LOG"),
+        "-A PROTONVPN_RS_OUTPUT -d 10.0.0.1 -j LOG"
+    );
+}
+
+#[test]
+fn test_nftables_ruleset() {
+    let custom =
+        vec!["add rule inet protonvpn_rs output ip daddr 192.168.0.0/24 accept".to_string()];
+    let ruleset = Nftables::ruleset(&policy(Protocol::Tcp, &custom));
+
+    // The table is declared and deleted first so applying the script replaces it
+    assert!(ruleset.starts_with("table inet protonvpn_rs\ndelete table inet protonvpn_rs\n"));
+    assert!(ruleset.contains(r#"oifname "tun0" accept"#));
+    assert!(
+        ruleset.contains("ip daddr { 10.0.0.1, 10.0.0.2 } tcp dport { 8443, 443, 7770 } accept")
+    );
+    assert!(
+        ruleset.contains("ip saddr { 10.0.0.1, 10.0.0.2 } tcp sport { 8443, 443, 7770 } accept")
+    );
+
+    assert!(ruleset.contains("policy drop;"));
+    assert!(ruleset.ends_with(&format!("{}\n", custom[0])));
+
+    let ruleset = Nftables::ruleset(&Policy {
+        entry_ips: Vec::new(),
+        ..policy(Protocol::Tcp, &[])
+    });
+    assert!(!ruleset.contains("dport"));
+}
+
+#[test]
+fn test_lockdown_ruleset() {
+    let ruleset = Iptables::ruleset(Family::V4, &lockdown(), &ALL_JUMPS);
+    let lines = ruleset.lines().collect::<Vec<_>>();
+
+    // No tunnel yet, only loopback
+    assert!(!lines.iter().any(|line| line.contains("tun0")));
+    assert!(lines.contains(&"-A PROTONVPN_RS_OUTPUT -o lo -j RETURN"));
+    assert!(
+        lines.contains(&"-A PROTONVPN_RS_OUTPUT -d 10.0.0.1 -p udp -m udp --dport 1194 -j RETURN")
+    );
+    assert!(lines.contains(&"-A PROTONVPN_RS_OUTPUT -d 9.9.9.9 -p udp -m udp --dport 53 -j RETURN"));
+    assert!(lines.contains(&"-A PROTONVPN_RS_OUTPUT -d 9.9.9.9 -p tcp -m tcp --dport 53 -j RETURN"));
+    assert!(lines.contains(
+        &"-A PROTONVPN_RS_OUTPUT -d 185.159.159.148 -p tcp -m tcp --dport 443 -j RETURN"
+    ));
+    assert!(!lines
+        .iter()
+        .any(|line| line.contains("185.159.159.148") && line.contains("udp")));
+
+    // The host still has to get an address while everything else is blocked
+    assert!(lines.contains(&"-A PROTONVPN_RS_OUTPUT -p udp -m udp --sport 68 --dport 67 -j RETURN"));
+    assert!(lines.contains(&"-A PROTONVPN_RS_INPUT -p udp -m udp --sport 67 --dport 68 -j RETURN"));
+
+    let ruleset = Iptables::ruleset(Family::V6, &lockdown(), &ALL_JUMPS);
+    assert!(!ruleset.contains("9.9.9.9"));
+    assert!(
+        ruleset.contains("-A PROTONVPN_RS_INPUT -p ipv6-icmp -m icmp6 --icmpv6-type 134 -j RETURN")
+    );
+    assert!(
+        !Iptables::ruleset(Family::V6, &policy(Protocol::Udp, &[]), &ALL_JUMPS).contains("icmp6")
+    );
+    assert!(ruleset.contains("-A PROTONVPN_RS_OUTPUT -j DROP"));
+
+    let ruleset = Nftables::ruleset(&lockdown());
+    assert!(!ruleset.contains("tun0"));
+    assert!(ruleset.contains("ip daddr { 9.9.9.9 } udp dport { 53 } accept"));
+    assert!(ruleset.contains("ip daddr { 9.9.9.9 } tcp dport { 53 } accept"));
+    assert!(ruleset.contains("ip daddr { 185.159.159.148 } tcp dport { 443 } accept"));
+    assert!(ruleset.contains("udp sport 68 udp dport 67 accept"));
+    assert!(ruleset.contains("nd-router-advert"));
+    assert!(ruleset.contains(
+        "ip saddr { 10.0.0.1, 10.0.0.2 } udp sport { 5060, 4569, 80, 1194, 51820 } accept"
+    ));
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use protonvpn_rs::client::management::{parse_line, Management, Message, TunnelState};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::SocketAddrV4,
+    os::unix::net::UnixListener,
+    path::PathBuf,
+    sync::mpsc,
+    time::Duration,
+};
+
+/// Binds a socket that behaves like openvpn's management interface,
+/// every command it receives is forwarded over the returned channel.
+fn fake_management_server(
+    name: &str,
+    lines: &'static [&'static str],
+) -> (PathBuf, mpsc::Receiver<String>) {
+    let path =
+        std::env::temp_dir().join(format!("protonvpn-rs-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).expect("to bind fake management socket");
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("a client");
+        writeln!(
+            stream,
+            ">INFO:OpenVPN Management Interface Version 5 -- type 'help' for more info"
+        )
+        .unwrap();
+
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        for command in reader.lines().map_while(Result::ok) {
+            if command.starts_with("state on all") {
+                for line in lines {
+                    writeln!(stream, "{line}").unwrap();
+                }
+            }
+            let quit = command == "quit";
+            let _ = tx.send(command);
+
+            if quit {
+                break;
+            }
+        }
+    });
+
+    (path, rx)
+}
+
+#[test]
+fn test_parse_line() {
+    assert_eq!(
+        parse_line(">STATE:1722500000,CONNECTED,SUCCESS,10.96.0.2,185.107.56.10,1194,,"),
+        Some(Message::State {
+            state: TunnelState::Connected,
+            detail: "SUCCESS".into(),
+            tunnel_ip: Some("10.96.0.2".parse().unwrap()),
+            remote: Some("185.107.56.10:1194".parse().unwrap()),
+        })
+    );
+
+    assert_eq!(
+        parse_line("1722500000,WAIT,,,,,,"),
+        Some(Message::State {
+            state: TunnelState::Wait,
+            detail: "".into(),
+            tunnel_ip: None,
+            remote: None,
+        })
+    );
+
+    assert_eq!(
+        parse_line(">STATE:1722500000,CONNECTED,SUCCESS,10.96.0.2,2001:db8::10,1194,,"),
+        Some(Message::State {
+            state: TunnelState::Connected,
+            detail: "SUCCESS".into(),
+            tunnel_ip: Some("10.96.0.2".parse().unwrap()),
+            remote: None,
+        })
+    );
+
+    assert_eq!(
+        parse_line(">BYTECOUNT:1024,512"),
+        Some(Message::ByteCount {
+            bytes_in: 1024,
+            bytes_out: 512
+        })
+    );
+
+    assert_eq!(
+        parse_line(">PASSWORD:Verification Failed: 'Auth'"),
+        Some(Message::AuthFailed)
+    );
+
+    assert_eq!(parse_line(">INFO:OpenVPN Management Interface"), None);
+    assert_eq!(
+        parse_line("SUCCESS: real-time state notification set to ON"),
+        None
+    );
+    assert_eq!(parse_line("END"), None);
+}
+
+#[test]
+fn test_management_tracks_state() -> Result<()> {
+    let (path, commands) = fake_management_server(
+        "connected",
+        &[
+            "1722500000,CONNECTING,,,,,,",
+            "1722500001,AUTH,,,185.107.56.10,1194,,",
+            "END",
+            ">STATE:1722500002,GET_CONFIG,,,,,,",
+            ">STATE:1722500003,CONNECTED,SUCCESS,10.96.0.2,185.107.56.10,1194,,",
+            ">BYTECOUNT:2048,4096",
+        ],
+    );
+
+    let (tx, changes) = mpsc::channel();
+    let management = Management::connect(&path, Duration::from_secs(5), move |status| {
+        let _ = tx.send(status.state.clone());
+    })?;
+
+    let status = management
+        .wait_until(Duration::from_secs(5), |status| status.bytes_in == 2048)
+        .expect("status to be updated");
+
+    assert_eq!(status.state, TunnelState::Connected);
+    assert_eq!(status.tunnel_ip, Some("10.96.0.2".parse()?));
+    assert_eq!(
+        status.remote,
+        Some("185.107.56.10:1194".parse::<SocketAddrV4>()?)
+    );
+    assert_eq!(status.bytes_out, 4096);
+    assert!(!status.auth_failed);
+
+    let changes = changes.iter().take(4).collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            TunnelState::Connecting,
+            TunnelState::Auth,
+            TunnelState::GetConfig,
+            TunnelState::Connected
+        ]
+    );
+
+    management.send_command("quit")?;
+    let commands = commands.iter().take(3).collect::<Vec<_>>();
+    assert_eq!(commands, ["state on all", "bytecount 5", "quit"]);
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_management_unparsable_remote() -> Result<()> {
+    let (path, _commands) = fake_management_server(
+        "ipv6-remote",
+        &[
+            "1722500000,CONNECTING,,,,,,",
+            "END",
+            ">STATE:1722500001,CONNECTED,SUCCESS,10.96.0.2,2001:db8::10,1194,,",
+        ],
+    );
+
+    let management = Management::connect(&path, Duration::from_secs(5), |_| {})?;
+    let status = management
+        .wait_until(Duration::from_secs(5), |status| {
+            status.state == TunnelState::Connected
+        })
+        .expect("the state change to come through");
+
+    assert_eq!(status.tunnel_ip, Some("10.96.0.2".parse()?));
+    assert_eq!(status.remote, None);
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_management_auth_failed() -> Result<()> {
+    let (path, _commands) = fake_management_server(
+        "auth-failed",
+        &[
+            "1722500000,AUTH,,,,,,",
+            ">PASSWORD:Verification Failed: 'Auth'",
+            ">STATE:1722500001,EXITING,auth-failure,,,,,",
+        ],
+    );
+
+    let management = Management::connect(&path, Duration::from_secs(5), |_| {})?;
+    let status = management
+        .wait_until(Duration::from_secs(5), |status| {
+            status.state == TunnelState::Exiting
+        })
+        .expect("openvpn to exit");
+
+    assert!(status.auth_failed);
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_management_connect_timeout() {
+    let path = std::env::temp_dir().join("protonvpn-rs-does-not-exist.sock");
+    assert!(Management::connect(&path, Duration::from_millis(200), |_| {}).is_err());
+}
@@ -0,0 +1,117 @@
+use protonvpn_rs::{
+    client::{openvpn::Protocol, Pid},
+    config::Notifications,
+    notify::{Notifier, Urgency},
+    protocol::Event,
+};
+
+fn summaries(events: &[Event], config: &Notifications) -> Vec<String> {
+    let mut notifier = Notifier::default();
+    events
+        .iter()
+        .filter_map(|event| notifier.handle(event, config))
+        .map(|notification| notification.summary)
+        .collect()
+}
+
+#[test]
+fn test_notifications() {
+    let config = Notifications::default();
+    let connected = Event::Connected {
+        server: "NL#1".into(),
+        protocol: Protocol::Udp,
+    };
+    let exited = |reason: Option<&str>| Event::OpenvpnExited {
+        pid: Pid::from(1234),
+        reason: reason.map(String::from),
+        unexpected: true,
+    };
+    let stopped = Event::OpenvpnExited {
+        pid: Pid::from(1234),
+        reason: None,
+        unexpected: false,
+    };
+
+    // disconnected by the user
+    assert_eq!(
+        summaries(&[connected.clone(), stopped, Event::Disconnected], &config),
+        ["VPN connected", "VPN disconnected"]
+    );
+
+    // killed or crashed without openvpn logging a reason
+    assert_eq!(
+        summaries(
+            &[connected.clone(), exited(None), Event::Disconnected],
+            &config
+        ),
+        ["VPN connected", "VPN connection dropped"]
+    );
+
+    // dropped connections aren't also reported as a disconnect
+    assert_eq!(
+        summaries(
+            &[
+                connected.clone(),
+                exited(Some("Inactivity timeout")),
+                Event::Disconnected
+            ],
+            &config
+        ),
+        ["VPN connected", "VPN connection dropped"]
+    );
+
+    // neither are authentication failures
+    assert_eq!(
+        summaries(
+            &[
+                Event::AuthFailed {
+                    server: "NL#1".into()
+                },
+                exited(Some("authentication failed")),
+                Event::Disconnected,
+                connected.clone(),
+                exited(Some("Inactivity timeout")),
+            ],
+            &config
+        ),
+        [
+            "VPN authentication failed",
+            "VPN connected",
+            "VPN connection dropped"
+        ]
+    );
+
+    let config = Notifications {
+        connected: false,
+        killswitch: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        summaries(
+            &[connected, Event::Killswitch(true), Event::Disconnected],
+            &config
+        ),
+        ["VPN disconnected"]
+    );
+}
+
+#[test]
+fn test_notification_gdbus_args() {
+    let mut notifier = Notifier::default();
+    let notification = notifier
+        .handle(
+            &Event::OpenvpnExited {
+                pid: Pid::from(1234),
+                reason: Some("can't resolve 'nl-01'".into()),
+                unexpected: true,
+            },
+            &Notifications::default(),
+        )
+        .expect("a notification");
+    assert_eq!(notification.urgency, Urgency::Critical);
+
+    let args = notification.gdbus_args();
+    assert!(args.contains(&"'VPN connection dropped'".to_string()));
+    assert!(args.contains(&r"'can\'t resolve \'nl-01\''".to_string()));
+    assert_eq!(args.last().map(String::as_str), Some("int32 -1"));
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use protonvpn_rs::{
+    client::{openvpn::Protocol, Pid},
+    daemon::persist::{LastUsed, PersistedConnection, PersistedState},
+};
+
+#[test]
+fn test_persisted_state_roundtrip() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("protonvpn-rs-{}-state.ron", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(PersistedState::read_from(&path)?, None);
+
+    let state = PersistedState {
+        connection: Some(PersistedConnection {
+            server: "server:with:colons".into(),
+            protocol: Protocol::Tcp,
+            pid: Pid::from(1234),
+            connected_at: 1722500000,
+        }),
+        killswitch: true,
+        last_used: Some(LastUsed {
+            server: "server:with:colons".into(),
+            protocol: Protocol::Tcp,
+        }),
+    };
+    state.write_to(&path)?;
+    assert_eq!(PersistedState::read_from(&path)?, Some(state));
+
+    // files written by older versions might be missing fields
+    std::fs::write(&path, "(killswitch: true)")?;
+    assert_eq!(
+        PersistedState::read_from(&path)?,
+        Some(PersistedState {
+            connection: None,
+            killswitch: true,
+            last_used: None,
+        })
+    );
+
+    std::fs::write(&path, "garbage")?;
+    assert!(PersistedState::read_from(&path).is_err());
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
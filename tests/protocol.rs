@@ -1,93 +1,230 @@
 use anyhow::Result;
 use protonvpn_rs::{
-    client::{openvpn::Protocol, Pid},
-    protocol::{Request, Response, ServerStatus, SocketProtocol},
+    api::{Country, Features, LogicalServer, Ordering, Server, Tier},
+    client::{
+        management::{TunnelState, TunnelStatus},
+        openvpn::{ConnectError, Protocol},
+        Pid,
+    },
+    config::{FeatureEnum, Filters, Select},
+    history::{Filter, Session},
+    protocol::{
+        read_frame, write_frame, ConnectionInfo, DaemonError, ErrorCode, Event, FailedAttempt,
+        Request, Response, ServerStatus, SocketProtocol, MAX_FRAME_LEN, PROTOCOL_VERSION,
+    },
 };
+use std::{io::Cursor, net::Ipv4Addr};
+
+fn filters() -> Filters {
+    Filters {
+        tier: Tier::Premium,
+        max_load: 90,
+        country: Some(Country::NL),
+        features: vec![FeatureEnum::P2P],
+    }
+}
 
 #[test]
-fn test_request_deserialize() -> Result<()> {
-    let request = Request::deserialize("status")?;
-    assert_eq!(request, Request::Status);
-
-    let request = Request::deserialize("disconnect")?;
-    assert_eq!(request, Request::Disconnect);
-
-    let request = Request::deserialize("connect:server1:udp")?;
-    assert_eq!(request, Request::Connect("server1".into(), Protocol::Udp));
-
-    let request = Request::deserialize("killswitch:true")?;
-    assert_eq!(request, Request::Killswitch(true));
+fn test_request_roundtrip() -> Result<()> {
+    let requests = [
+        Request::Hello {
+            version: PROTOCOL_VERSION,
+        },
+        Request::Status,
+        Request::Disconnect,
+        Request::Connect(
+            vec!["server:with:colons".into(), "server2".into()],
+            Protocol::Udp,
+        ),
+        Request::ConnectMatching(filters(), Select::LeastLoad, Protocol::Tcp),
+        Request::Query(filters(), Some(Ordering::Load), Some(5)),
+        Request::Query(filters(), None, None),
+        Request::Killswitch(true),
+        Request::RefreshServers,
+        Request::History(Filter {
+            server: Some("NL".into()),
+            protocol: Some(Protocol::Tcp),
+            since: Some(1700000000),
+            limit: Some(10),
+        }),
+        Request::Subscribe,
+    ];
+
+    for request in requests {
+        assert_eq!(Request::deserialize(&request.serialize())?, request);
+    }
 
-    assert!(Request::deserialize("killswitch:tru").is_err());
+    Ok(())
+}
 
-    assert!(Request::deserialize("connect:server1").is_err());
+#[test]
+fn test_request_deserialize_invalid() {
+    assert!(Request::deserialize(b"status").is_err());
+    assert!(Request::deserialize(br#"{"Killswitch":"tru"}"#).is_err());
+    assert!(Request::deserialize(br#"{"Connect":[["server1"]]}"#).is_err());
+    assert!(Request::deserialize(br#"{"Connect":[["server1"],"quic"]}"#).is_err());
+    assert!(Request::deserialize(br#""Unknown""#).is_err());
+}
 
-    assert!(Request::deserialize("unknown:command").is_err());
+#[test]
+fn test_response_roundtrip() -> Result<()> {
+    let responses = [
+        Response::Hello {
+            version: PROTOCOL_VERSION,
+        },
+        Response::Ok,
+        Response::Status(ServerStatus::Disconnected { last_exit: None }),
+        Response::Status(ServerStatus::Disconnected {
+            last_exit: Some("process exiting".into()),
+        }),
+        Response::Status(ServerStatus::Connecting {
+            name: "NL#1".into(),
+            protocol: Protocol::Udp,
+        }),
+        Response::Status(ServerStatus::Connected(ConnectionInfo {
+            pid: Pid::try_from("1234".to_string())?,
+            name: "server1".into(),
+            protocol: Protocol::Tcp,
+            state: Some(TunnelState::Reconnecting),
+            connected_since: 1722500000,
+            interface: Some("tun0".into()),
+            tunnel_ip: Some("10.96.0.2".parse()?),
+            remote: Some("185.107.56.10:1194".parse()?),
+            bytes_in: 2048,
+            bytes_out: 4096,
+            killswitch: true,
+            country: Country::NL,
+            load: 42,
+            features: Features::P2P | Features::Streaming,
+        })),
+        Response::Connected {
+            server: "NL#2".into(),
+            protocol: Protocol::Tcp,
+            failed: vec![FailedAttempt {
+                server: "NL#1".into(),
+                protocol: Protocol::Udp,
+                error: "tunnel didn't come up in time".into(),
+            }],
+        },
+        Response::Event(Event::Connecting {
+            server: "NL#1".into(),
+            protocol: Protocol::Udp,
+        }),
+        Response::Event(Event::OpenvpnExited {
+            pid: Pid::try_from("1234".to_string())?,
+            reason: Some("SIGTERM[hard,] received, process exiting".into()),
+            unexpected: true,
+        }),
+        Response::Servers(vec![LogicalServer {
+            name: "NL#1".into(),
+            exit_country: Country::NL,
+            tier: 2,
+            features: Features::P2P,
+            score: 1.5,
+            id: "abc".into(),
+            status: 1,
+            servers: vec![Server {
+                entry_ip: Ipv4Addr::new(10, 0, 0, 1),
+            }],
+            load: 40,
+        }]),
+        Response::Refreshed { servers: 1234 },
+        Response::History(vec![Session {
+            server: "NL#1".into(),
+            server_id: "abc".into(),
+            protocol: Protocol::Udp,
+            started_at: 1700000000,
+            ended_at: 1700003600,
+            reason: "disconnected".into(),
+            bytes_in: 1024,
+            bytes_out: 512,
+        }]),
+        Response::Event(Event::Killswitch(true)),
+        Response::Event(Event::ServersRefreshed { servers: 1234 }),
+        Response::Event(Event::Tunnel(TunnelState::Unknown("AUTH_PENDING".into()))),
+        Response::Error {
+            code: ErrorCode::ServerNotFound,
+            message: "No server found with id: abc".into(),
+        },
+    ];
+
+    for response in responses {
+        assert_eq!(Response::deserialize(&response.serialize())?, response);
+    }
 
     Ok(())
 }
 
 #[test]
-fn test_request_serialize() -> Result<()> {
-    let request = Request::Status;
-    assert_eq!(request.serialize(), b"status".to_vec());
-
-    let request = Request::Disconnect;
-    assert_eq!(request.serialize(), b"disconnect".to_vec());
+fn test_response_into_result() {
+    assert!(Response::Ok.into_result().is_ok());
 
-    let request = Request::Connect("server1".into(), Protocol::Udp);
-    assert_eq!(request.serialize(), b"connect:server1:udp".to_vec());
+    let err = Response::Error {
+        code: ErrorCode::NoActiveConnection,
+        message: "not connected".into(),
+    }
+    .into_result()
+    .unwrap_err();
 
-    let request = Request::Killswitch(true);
-    assert_eq!(request.serialize(), b"killswitch:true".to_vec());
+    let err = err.downcast_ref::<DaemonError>().expect("a daemon error");
+    assert_eq!(err.code, ErrorCode::NoActiveConnection);
+    assert_eq!(err.message, "not connected");
+}
 
-    Ok(())
+#[test]
+fn test_connect_error_codes() {
+    let err = DaemonError::from(ConnectError::AuthFailed);
+    assert_eq!(err.code, ErrorCode::AuthFailed);
+
+    let err = DaemonError::from(ConnectError::BinaryMissing);
+    assert_eq!(err.code, ErrorCode::OpenvpnMissing);
+
+    let status = TunnelStatus {
+        state: TunnelState::Reconnecting,
+        last_error: Some("tls-error".into()),
+        ..Default::default()
+    };
+    let err = DaemonError::from(ConnectError::Timeout(status));
+    assert_eq!(err.code, ErrorCode::ConnectTimeout);
+    assert!(err.message.contains("Reconnecting (tls-error)"));
 }
 
 #[test]
-fn test_response_deserialize() -> Result<()> {
-    let response = Response::deserialize("status:disconnected")?;
-    assert!(matches!(
-        response,
-        Response::Status(ServerStatus::Disconnected)
-    ));
-
-    let response = Response::deserialize("status:connected:1234:server1:udp")?;
-    if let Response::Status(ServerStatus::Connected {
-        pid,
-        name,
-        protocol,
-    }) = response
-    {
-        assert_eq!(pid.to_string(), "1234");
-        assert_eq!(name, "server1");
-        assert_eq!(protocol, Protocol::Udp);
-    } else {
-        panic!("Expected connected status");
-    }
+fn test_frames() -> Result<()> {
+    let mut buf = Vec::new();
+    Request::Status.write_to(&mut buf)?;
+    Request::Killswitch(false).write_to(&mut buf)?;
 
-    assert!(Response::deserialize("status:invalid:command").is_err());
+    let len = u32::from_be_bytes(buf[..4].try_into()?) as usize;
+    assert_eq!(&buf[4..4 + len], Request::Status.serialize().as_slice());
 
-    assert!(Response::deserialize("unknown:command").is_err());
+    let mut reader = Cursor::new(buf);
+    assert_eq!(Request::read_from(&mut reader)?, Some(Request::Status));
+    assert_eq!(
+        Request::read_from(&mut reader)?,
+        Some(Request::Killswitch(false))
+    );
+    assert_eq!(Request::read_from(&mut reader)?, None);
 
     Ok(())
 }
 
 #[test]
-fn test_response_serialize() -> Result<()> {
-    let response = Response::Status(ServerStatus::Disconnected);
-    assert_eq!(response.serialize(), b"status:disconnected".to_vec());
-
-    let pid = Pid::try_from("1234".to_string())?;
-    let response = Response::Status(ServerStatus::Connected {
-        pid,
-        name: "server1".into(),
-        protocol: Protocol::Udp,
-    });
-    assert_eq!(
-        response.serialize(),
-        b"status:connected:1234:server1:udp".to_vec()
-    );
+fn test_frames_invalid() -> Result<()> {
+    // length prefix larger than the maximum frame size
+    let mut reader = Cursor::new((MAX_FRAME_LEN + 1).to_be_bytes().to_vec());
+    assert!(read_frame(&mut reader).is_err());
+
+    // connection closed halfway through a frame
+    let mut buf = Vec::new();
+    write_frame(&mut buf, b"\"Status\"")?;
+    buf.truncate(buf.len() - 2);
+    assert!(read_frame(&mut Cursor::new(buf)).is_err());
+
+    // valid frame with a payload that isn't a request
+    let mut buf = Vec::new();
+    write_frame(&mut buf, b"connect:server1:udp")?;
+    assert!(Request::read_from(&mut Cursor::new(buf)).is_err());
 
     Ok(())
 }
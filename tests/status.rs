@@ -0,0 +1,117 @@
+use anyhow::Result;
+use protonvpn_rs::{
+    api::{Country, Features},
+    cli::{bar_output, country_flag, Bar, StatusReport},
+    client::{management::TunnelState, openvpn::Protocol, Pid},
+    protocol::ConnectionInfo,
+};
+use serde_json::json;
+
+fn connected() -> StatusReport {
+    StatusReport::Connected(ConnectionInfo {
+        name: "NL#1".into(),
+        pid: Pid::from(1234),
+        protocol: Protocol::Udp,
+        state: Some(TunnelState::Connected),
+        connected_since: 1722500000,
+        interface: Some("tun0".into()),
+        tunnel_ip: Some("10.96.0.2".parse().unwrap()),
+        remote: Some("185.107.56.10:1194".parse().unwrap()),
+        bytes_in: 2048,
+        bytes_out: 4096,
+        killswitch: false,
+        country: Country::NL,
+        load: 42,
+        features: Features::Streaming,
+    })
+}
+
+#[test]
+fn test_status_report_json() -> Result<()> {
+    let value = serde_json::to_value(connected())?;
+    assert_eq!(value["status"], "connected");
+    assert_eq!(value["name"], "NL#1");
+    assert_eq!(value["pid"], 1234);
+    assert_eq!(value["protocol"], "Udp");
+    assert_eq!(value["state"], "Connected");
+    assert_eq!(value["tunnel_ip"], "10.96.0.2");
+    assert_eq!(value["remote"], "185.107.56.10:1194");
+    assert_eq!(value["country"], "NL");
+
+    assert_eq!(
+        serde_json::to_value(StatusReport::Disconnected { last_exit: None })?,
+        json!({ "status": "disconnected", "last_exit": null })
+    );
+    assert_eq!(
+        serde_json::to_value(StatusReport::Disconnected {
+            last_exit: Some("connection reset".into())
+        })?,
+        json!({ "status": "disconnected", "last_exit": "connection reset" })
+    );
+    assert_eq!(
+        serde_json::to_value(StatusReport::Connecting {
+            name: "NL#1".into(),
+            protocol: Protocol::Tcp
+        })?,
+        json!({ "status": "connecting", "name": "NL#1", "protocol": "Tcp" })
+    );
+
+    assert!(ron::to_string(&connected())?.contains("status:\"connected\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_status_report_plain() -> Result<()> {
+    let lines = connected().plain_lines()?;
+    assert_eq!(lines.first(), Some(&("status".into(), "connected".into())));
+    assert!(lines.contains(&("name".into(), "NL#1".into())));
+    assert!(lines.contains(&("load".into(), "42".into())));
+    assert!(lines.contains(&("remote".into(), "185.107.56.10:1194".into())));
+
+    let disconnected = StatusReport::Disconnected { last_exit: None }.plain_lines()?;
+    assert_eq!(disconnected, [("status".into(), "disconnected".into())]);
+
+    Ok(())
+}
+
+#[test]
+fn test_status_exit_codes() {
+    assert_eq!(connected().exit_code(), 0);
+    assert_eq!(
+        StatusReport::Disconnected { last_exit: None }.exit_code(),
+        3
+    );
+    assert_eq!(
+        StatusReport::Unreachable {
+            error: "connection refused".into()
+        }
+        .exit_code(),
+        4
+    );
+}
+
+#[test]
+fn test_bar_output() -> Result<()> {
+    let waybar = serde_json::from_str::<serde_json::Value>(&bar_output(&connected(), Bar::Waybar))?;
+    assert_eq!(waybar["text"], "🇳🇱 NL#1");
+    assert_eq!(waybar["class"], "connected");
+    assert!(waybar["tooltip"].as_str().unwrap().contains("Load: 42%"));
+
+    assert_eq!(
+        bar_output(
+            &StatusReport::Disconnected { last_exit: None },
+            Bar::Polybar
+        ),
+        "%{F#bf616a}disconnected%{F-}"
+    );
+
+    let i3blocks =
+        serde_json::from_str::<serde_json::Value>(&bar_output(&connected(), Bar::I3blocks))?;
+    assert_eq!(i3blocks["full_text"], "🇳🇱 NL#1");
+    assert_eq!(i3blocks["short_text"], "NL");
+
+    assert_eq!(country_flag("UK"), "🇬🇧");
+
+    Ok(())
+}
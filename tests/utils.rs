@@ -0,0 +1,47 @@
+use protonvpn_rs::utils::{self, parse_group_id, wait_for_file_and_read};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_parse_group_id() {
+    let etc_group = "root:x:0:\nwheel:x:10:alice\nvpn:x:978:alice,bob\n";
+
+    assert_eq!(parse_group_id(etc_group, "root"), Some(0));
+    assert_eq!(parse_group_id(etc_group, "vpn"), Some(978));
+    assert_eq!(parse_group_id(etc_group, "1001"), Some(1001));
+    assert_eq!(parse_group_id(etc_group, "alice"), None);
+    assert_eq!(parse_group_id(etc_group, "missing"), None);
+}
+
+#[test]
+fn test_wait_for_empty_file_times_out() {
+    let dir = std::env::temp_dir().join(format!("protonvpn-rs-{}-wait", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("a temp dir");
+    let path = dir.join("openvpn.pid");
+    std::fs::write(&path, "").expect("an empty file");
+
+    let start = Instant::now();
+    let result = wait_for_file_and_read(path.to_str().unwrap(), Duration::from_millis(200));
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    std::fs::write(&path, "1234\n").expect("a pid");
+    let result = wait_for_file_and_read(path.to_str().unwrap(), Duration::from_millis(200));
+    assert_eq!(result.ok().as_deref(), Some("1234\n"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_formatting() {
+    assert_eq!(utils::format_timestamp(0), "1970-01-01 00:00");
+    assert_eq!(utils::format_timestamp(1722500000), "2024-08-01 08:13");
+    assert_eq!(utils::format_timestamp(951782400), "2000-02-29 00:00");
+
+    assert_eq!(utils::format_duration(Duration::from_secs(42)), "42s");
+    assert_eq!(utils::format_duration(Duration::from_secs(125)), "2m 05s");
+    assert_eq!(utils::format_duration(Duration::from_secs(3725)), "1h 02m");
+
+    assert_eq!(utils::format_bytes(512), "512 B");
+    assert_eq!(utils::format_bytes(1536), "1.5 KiB");
+    assert_eq!(utils::format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+}